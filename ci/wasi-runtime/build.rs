@@ -0,0 +1,19 @@
+use lute_src_rs_common::{Backend, LConfig, LinkModifier, commonflags, finalize};
+
+fn main() {
+    // The lute sources are expected next to lute-src-rs-common's manifest
+    std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/../..")).unwrap();
+
+    let lcfg = LConfig {
+        backend: Backend::Cc,
+        // Link every object of the Lute runtime and a libuv using module, so
+        // that a libuv function missing from the stub fails the link
+        link_modifiers: &[
+            ("Lute.Runtime", &[LinkModifier::WholeArchive(true)]),
+            ("Lute.Fs", &[LinkModifier::WholeArchive(true)]),
+        ],
+        ..Default::default()
+    };
+    commonflags::build_lute_from_source(lcfg, false);
+    finalize::finalize_build(lcfg, false);
+}
@@ -0,0 +1,42 @@
+use std::ffi::{CStr, c_char, c_int, c_void};
+
+#[repr(C)]
+struct LuaState {
+    _private: [u8; 0],
+}
+
+unsafe extern "C" {
+    fn luaL_newstate() -> *mut LuaState;
+    fn luaL_openlibs(state: *mut LuaState);
+    fn luau_compile(source: *const c_char, size: usize, options: *mut c_void, outsize: *mut usize) -> *mut c_char;
+    fn luau_load(state: *mut LuaState, chunkname: *const c_char, data: *const c_char, size: usize, env: c_int) -> c_int;
+    fn lua_pcall(state: *mut LuaState, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int;
+    fn lua_tonumberx(state: *mut LuaState, idx: c_int, isnum: *mut c_int) -> f64;
+    fn lua_tolstring(state: *mut LuaState, idx: c_int, len: *mut usize) -> *const c_char;
+    fn lua_close(state: *mut LuaState);
+}
+
+// Raising and catching a Luau error goes through the wasm exception handling
+// the runtime is compiled with
+const SCRIPT: &str = r#"
+local ok, err = pcall(function() error("boom") end)
+assert(not ok and string.find(err, "boom"))
+return 6 * 7
+"#;
+
+fn main() {
+    unsafe {
+        let state = luaL_newstate();
+        luaL_openlibs(state);
+
+        let mut size = 0;
+        let bytecode = luau_compile(SCRIPT.as_ptr().cast(), SCRIPT.len(), std::ptr::null_mut(), &mut size);
+        if luau_load(state, c"=check".as_ptr(), bytecode, size, 0) != 0 || lua_pcall(state, 0, 1, 0) != 0 {
+            let err = CStr::from_ptr(lua_tolstring(state, -1, std::ptr::null_mut()));
+            panic!("The script failed: {}", err.to_string_lossy());
+        }
+        assert_eq!(lua_tonumberx(state, -1, std::ptr::null_mut()), 42.0);
+        lua_close(state);
+    }
+    println!("ok");
+}
@@ -0,0 +1,369 @@
+//! Post-build inspection of the Lute static libraries.
+//!
+//! A Lute build silently omitting a module (e.g. a `LUTE_DISABLE_*` mismatch
+//! between the build and the link list) otherwise only surfaces as undefined
+//! symbols from the Rust linker. After the CMake build or the prebuilt
+//! reassembly, [`verify_lute_archives`] reads the symbols of every archive of
+//! the link list (from the `ar` symbol index with a GNU, BSD or COFF layout,
+//! as written by `ar`/`ranlib`/`llvm-ar`/`lib.exe`, or else from the ELF,
+//! Mach-O or COFF symbol tables of its members) and checks that each exists,
+//! that the enabled modules export their expected entry points and that no C
+//! symbol is defined by several libraries.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::finalize::{LinkKind, static_link_libraries};
+use crate::object::{self, read_uint};
+use crate::search::find_static_lib;
+use crate::LConfig;
+
+/// Entry points each library must define, when in the link list. The Lute
+/// modules' `luaopen_*` functions are C++ ones, so their mangled names
+/// (Itanium or MSVC) are accepted as well.
+pub const EXPECTED_SYMBOLS: &[(&str, &[&str])] = &[
+    ("Luau.VM", &["lua_newstate", "luaL_openlibs"]),
+    ("Luau.Compiler", &["luau_compile"]),
+    ("Luau.CodeGen", &["luau_codegen_supported", "luau_codegen_create"]),
+    ("Lute.Crypto", &["luaopen_crypto"]),
+    ("Lute.Fs", &["luaopen_fs"]),
+    ("Lute.Luau", &["luaopen_luau"]),
+    ("Lute.Net", &["luaopen_net"]),
+    ("Lute.Process", &["luaopen_process"]),
+    ("Lute.System", &["luaopen_system"]),
+    ("Lute.Task", &["luaopen_task"]),
+    ("Lute.Time", &["luaopen_time"]),
+    ("Lute.VM", &["luaopen_vm"]),
+];
+
+/// Returns the global symbols defined by the `ar` archive `data`, read from
+/// its symbol index (GNU/COFF `/`, GNU `/SYM64/` or BSD `__.SYMDEF`) or, for
+/// archives without one, from the symbol tables of its object files.
+///
+/// Returns `Err` if `data` is not an archive or neither has an index nor
+/// contains readable object files.
+pub fn archive_symbols(data: &[u8]) -> Result<Vec<String>, String> {
+    let thin = data.starts_with(b"!<thin>\n");
+    if !data.starts_with(b"!<arch>\n") && !thin {
+        return Err("not an ar archive".to_string());
+    }
+
+    let mut members = Vec::new();
+    let mut pos = 8;
+    while pos + 60 <= data.len() {
+        let header = &data[pos..pos + 60];
+        let name = String::from_utf8_lossy(&header[..16]).trim_end().to_string();
+        let size = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("corrupt member header at offset {pos}"))?;
+        // The members of thin archives are stored outside of them
+        let stored = if thin && !matches!(name.as_str(), "/" | "//" | "/SYM64/") { 0 } else { size };
+        let mut body = data
+            .get(pos + 60..pos + 60 + stored)
+            .ok_or_else(|| format!("truncated member at offset {pos}"))?;
+
+        // BSD long names are stored at the start of the member data
+        let name = match name.strip_prefix("#1/").and_then(|len| len.parse::<usize>().ok()) {
+            Some(len) if len <= body.len() => {
+                let long_name = String::from_utf8_lossy(&body[..len]).trim_end_matches('\0').to_string();
+                body = &body[len..];
+                long_name
+            }
+            _ => name,
+        };
+
+        match name.as_str() {
+            // The index is the first member (GNU long names may precede it in
+            // some writers)
+            "/" if members.is_empty() => return gnu_index(body, 4),
+            "/SYM64/" if members.is_empty() => return gnu_index(body, 8),
+            "__.SYMDEF" | "__.SYMDEF SORTED" if members.is_empty() => return bsd_index(body, 4),
+            "__.SYMDEF_64" | "__.SYMDEF_64 SORTED" if members.is_empty() => return bsd_index(body, 8),
+            // The second linker member and long names table of COFF archives
+            "/" | "//" => {}
+            _ => members.push(body),
+        }
+        pos += 60 + stored + stored % 2;
+    }
+
+    let mut symbols = Vec::new();
+    let mut readable = false;
+    for member in members {
+        if let Some(member_symbols) = object::defined_symbols(member) {
+            symbols.extend(member_symbols);
+            readable = true;
+        }
+    }
+    if readable {
+        Ok(symbols)
+    } else {
+        Err("no symbol index (run ranlib on it)".to_string())
+    }
+}
+
+// Big endian count and member offsets, then the NUL terminated names
+fn gnu_index(body: &[u8], width: usize) -> Result<Vec<String>, String> {
+    let count = read_uint(body, 0, width, false).ok_or("truncated symbol index")? as usize;
+    let names = body
+        .get(width * (count + 1)..)
+        .ok_or("truncated symbol index")?;
+    Ok(names
+        .split(|&b| b == 0)
+        .take(count)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .collect())
+}
+
+// Little endian (as written for the Apple/BSD targets) size of the
+// (name offset, member offset) entries, then the string table
+fn bsd_index(body: &[u8], width: usize) -> Result<Vec<String>, String> {
+    let entries_size = read_uint(body, 0, width, true).ok_or("truncated symbol index")? as usize;
+    let strings_start = width + entries_size;
+    let strings_size = read_uint(body, strings_start, width, true).ok_or("truncated symbol index")? as usize;
+    let strings = body
+        .get(strings_start + width..strings_start + width + strings_size)
+        .ok_or("truncated symbol index")?;
+
+    let mut symbols = Vec::new();
+    for entry in (width..width + entries_size).step_by(2 * width) {
+        let offset = read_uint(body, entry, width, true).ok_or("truncated symbol index")? as usize;
+        let name = strings.get(offset..).ok_or("corrupt symbol index")?;
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        symbols.push(String::from_utf8_lossy(&name[..end]).to_string());
+    }
+    Ok(symbols)
+}
+
+/// Checks the Luau/Lute archives of the link list for `lcfg` in the
+/// registered library directories, panicking if one is missing or lacks an
+/// expected entry point and warning about C symbols defined by several of
+/// them.
+pub fn verify_lute_archives(lcfg: &LConfig, prebuilt: bool) {
+    let target = std::env::var("TARGET").unwrap();
+    // Mach-O prepends `_` to C symbol names (and 32-bit Windows)
+    let underscore = if target.contains("-apple-") || target.starts_with("i686-pc-windows") { "_" } else { "" };
+
+    let mut missing_libs = Vec::new();
+    let mut problems = Vec::new();
+    // C symbol -> libraries defining it
+    let mut definitions: HashMap<String, Vec<String>> = HashMap::new();
+    for lib in static_link_libraries(*lcfg, prebuilt) {
+        if lib.kind != LinkKind::Static || !(lib.name.starts_with("Luau.") || lib.name.starts_with("Lute.")) {
+            continue;
+        }
+        let Some(path) = find_static_lib(&lib.name, &target) else {
+            missing_libs.push(lib.name);
+            continue;
+        };
+
+        let symbols = match read_archive_symbols(&path) {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                problems.push(format!("{}: {}", path.display(), e));
+                continue;
+            }
+        };
+        let mut c_symbols = symbols
+            .iter()
+            .filter_map(|symbol| symbol.strip_prefix(underscore))
+            // Mangled C++ names (inline functions and templates are defined
+            // by every library using them)
+            .filter(|name| !name.starts_with("_Z") && !name.starts_with('?') && !name.starts_with("__"))
+            .collect::<Vec<_>>();
+        c_symbols.sort_unstable();
+        c_symbols.dedup();
+
+        if let Some((_, expected)) = EXPECTED_SYMBOLS.iter().find(|(name, _)| *name == lib.name) {
+            for symbol in *expected {
+                let found = symbols.iter().any(|defined| {
+                    // Mach-O prepends `_` to every name, 32-bit Windows to C
+                    // names only, and the archives may have been prefixed in
+                    // place by an earlier run
+                    let defined = defined.strip_prefix(underscore).unwrap_or(defined);
+                    let defined = lcfg
+                        .symbol_prefix
+                        .and_then(|prefix| defined.strip_prefix(prefix))
+                        .unwrap_or(defined);
+                    defines(defined, symbol)
+                });
+                if !found {
+                    problems.push(format!("{} does not define {}", lib.name, symbol));
+                }
+            }
+        }
+        for name in c_symbols {
+            definitions.entry(name.to_string()).or_default().push(lib.name.clone());
+        }
+    }
+
+    if !missing_libs.is_empty() {
+        panic!(
+            "The Lute build did not produce {} (searched {:?}); check that the LConfig module \
+             switches match the build (LUTE_DISABLE_*)",
+            missing_libs.join(", "),
+            crate::search::lib_dirs()
+        );
+    }
+    if !problems.is_empty() {
+        panic!("The Lute libraries are incomplete:\n  {}", problems.join("\n  "));
+    }
+
+    let mut duplicates = definitions
+        .into_iter()
+        .filter(|(_, libs)| libs.len() > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    for (symbol, libs) in duplicates {
+        println!("cargo:warning={} is defined by several Lute libraries: {}", symbol, libs.join(", "));
+    }
+}
+
+// Checks if the symbol `defined` is the function `name`, either as a C
+// function or a C++ one with Itanium (`_Z10luaopen_fsP9lua_State`) or MSVC
+// (`?luaopen_fs@@YAHPEAUlua_State@@@Z`) mangling
+fn defines(defined: &str, name: &str) -> bool {
+    defined == name
+        || defined
+            .strip_prefix(&format!("_Z{}{}", name.len(), name))
+            .is_some_and(|params| !params.is_empty())
+        || defined.starts_with(&format!("?{name}@@"))
+}
+
+fn read_archive_symbols(path: &Path) -> Result<Vec<String>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    archive_symbols(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, body: &[u8]) -> Vec<u8> {
+        let mut member = format!("{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", 0, 0, 0, 644, body.len()).into_bytes();
+        member.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            member.push(b'\n');
+        }
+        member
+    }
+
+    fn archive(members: &[Vec<u8>]) -> Vec<u8> {
+        let mut archive = b"!<arch>\n".to_vec();
+        for member in members {
+            archive.extend_from_slice(member);
+        }
+        archive
+    }
+
+    // A 64-bit little endian ELF relocatable defining `symbols` as globals,
+    // with a local symbol and an undefined one besides
+    fn elf_object(symbols: &[&str]) -> Vec<u8> {
+        let mut strtab = b"\0local\0undefined\0".to_vec();
+        let mut symtab = vec![0u8; 24];
+        let mut symbol = |name: usize, info: u8, shndx: u16| {
+            let mut sym = [0u8; 24];
+            sym[..4].copy_from_slice(&(name as u32).to_le_bytes());
+            sym[4] = info;
+            sym[6..8].copy_from_slice(&shndx.to_le_bytes());
+            symtab.extend_from_slice(&sym);
+        };
+        symbol(1, 0x02, 1);
+        symbol(7, 0x10, 0);
+        for name in symbols {
+            symbol(strtab.len(), 0x12, 1);
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+
+        let symtab_offset = 64 + 3 * 64;
+        let strtab_offset = symtab_offset + symtab.len();
+        let mut elf = vec![0u8; 64];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[0x28..0x30].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+        // Null section, then the symbol table linked to the string table
+        elf.extend_from_slice(&[0u8; 64]);
+        for (kind, offset, size, link) in [(2u32, symtab_offset, symtab.len(), 2u32), (3, strtab_offset, strtab.len(), 0)] {
+            let mut sh = [0u8; 64];
+            sh[0x04..0x08].copy_from_slice(&kind.to_le_bytes());
+            sh[0x18..0x20].copy_from_slice(&(offset as u64).to_le_bytes());
+            sh[0x20..0x28].copy_from_slice(&(size as u64).to_le_bytes());
+            sh[0x28..0x2c].copy_from_slice(&link.to_le_bytes());
+            elf.extend_from_slice(&sh);
+        }
+        elf.extend_from_slice(&symtab);
+        elf.extend_from_slice(&strtab);
+        elf
+    }
+
+    #[test]
+    fn gnu_index() {
+        let mut index = 2u32.to_be_bytes().to_vec();
+        index.extend_from_slice(&[0, 0, 0, 80, 0, 0, 0, 80]);
+        index.extend_from_slice(b"lua_newstate\0luaL_openlibs\0");
+        let data = archive(&[member("/", &index), member("lapi.o/", b"\x7fELF")]);
+        assert_eq!(archive_symbols(&data).unwrap(), ["lua_newstate", "luaL_openlibs"]);
+
+        let mut index = 1u64.to_be_bytes().to_vec();
+        index.extend_from_slice(&80u64.to_be_bytes());
+        index.extend_from_slice(b"luau_compile\0");
+        let data = archive(&[member("//", b"a_long_member_name.o/\n"), member("/SYM64/", &index)]);
+        assert_eq!(archive_symbols(&data).unwrap(), ["luau_compile"]);
+    }
+
+    #[test]
+    fn bsd_index() {
+        let strings = b"_lua_newstate\0_luaL_openlibs\0\0\0";
+        let mut index = 16u32.to_le_bytes().to_vec();
+        for (name, member) in [(0u32, 80u32), (14, 80)] {
+            index.extend_from_slice(&name.to_le_bytes());
+            index.extend_from_slice(&member.to_le_bytes());
+        }
+        index.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        index.extend_from_slice(strings);
+        // With a BSD long name, stored at the start of the member data
+        let mut body = b"__.SYMDEF SORTED\0\0\0\0".to_vec();
+        body.extend_from_slice(&index);
+        let data = archive(&[member("#1/20", &body)]);
+        assert_eq!(archive_symbols(&data).unwrap(), ["_lua_newstate", "_luaL_openlibs"]);
+
+        let mut index = 16u64.to_le_bytes().to_vec();
+        index.extend_from_slice(&[0u8; 8]);
+        index.extend_from_slice(&80u64.to_le_bytes());
+        index.extend_from_slice(&8u64.to_le_bytes());
+        index.extend_from_slice(b"_luau_c\0");
+        let data = archive(&[member("__.SYMDEF_64", &index)]);
+        assert_eq!(archive_symbols(&data).unwrap(), ["_luau_c"]);
+    }
+
+    #[test]
+    fn member_symbols_without_index() {
+        let data = archive(&[
+            member("fs.cpp.o/", &elf_object(&["luaopen_fs", "_Z10luteopen_fsP9lua_State"])),
+            member("bitcode.o/", b"BC\xc0\xde"),
+            member("vm.cpp.o/", &elf_object(&["luaopen_vm"])),
+        ]);
+        assert_eq!(
+            archive_symbols(&data).unwrap(),
+            ["luaopen_fs", "_Z10luteopen_fsP9lua_State", "luaopen_vm"]
+        );
+
+        let data = archive(&[member("bitcode.o/", b"BC\xc0\xde")]);
+        assert!(archive_symbols(&data).is_err());
+        assert!(archive_symbols(b"\x7fELF").is_err());
+        assert!(archive_symbols(b"!<arch>\n/               0           0     0     644     99        `\n").is_err());
+    }
+
+    #[test]
+    fn mangled_entry_points() {
+        assert!(defines("luaopen_fs", "luaopen_fs"));
+        assert!(defines("_Z10luaopen_fsP9lua_State", "luaopen_fs"));
+        assert!(defines("?luaopen_fs@@YAHPEAUlua_State@@@Z", "luaopen_fs"));
+        assert!(!defines("luaopen_fs2", "luaopen_fs"));
+        assert!(!defines("_Z10luaopen_fs", "luaopen_fs"));
+        assert!(!defines("_Z11luaopen_fsxP9lua_State", "luaopen_fs"));
+        assert!(!defines("?luaopen_fs@Lute@@YAHPEAUlua_State@@@Z", "luaopen_fs"));
+    }
+}
@@ -0,0 +1,100 @@
+//! Rust FFI bindings for the Luau and Lute C APIs (`bindgen` feature).
+//!
+//! The bindings are generated with bindgen from the headers and defines the
+//! `cc` builds of the runtime use ([`lute_cc_include_paths`],
+//! [`lute_cc_defines`]), so they always match the compiled configuration.
+//! They are written to `OUT_DIR/lute_bindings.rs`, whose path is exported as
+//! the `LUTE_BINDINGS` environment variable of the crate being built (for
+//! `include!(env!("LUTE_BINDINGS"))`) and as the `bindings`
+//! [cargo metadata](crate#metadata-for-dependents) key (`DEP_LUTE_BINDINGS`).
+//!
+//! With [`LConfig::symbol_prefix`], the functions and variables get the
+//! prefixed `#[link_name]` while keeping their C names.
+//!
+//! bindgen loads libclang at runtime, see `LIBCLANG_PATH`.
+
+use std::path::PathBuf;
+
+use bindgen::callbacks::{ItemInfo, ItemKind, ParseCallbacks};
+
+use crate::LConfig;
+use crate::commonflags::{lute_cc_defines, lute_cc_include_paths};
+use crate::rerun::{print_once, rerun_if_changed};
+
+/// Headers of the Luau C API, always part of the bindings
+pub const LUAU_HEADERS: &[&str] = &[
+    "lute/extern/luau/VM/include/lua.h",
+    "lute/extern/luau/VM/include/lualib.h",
+    "lute/extern/luau/Compiler/include/luacode.h",
+];
+
+/// Header of the native code generation C API, part of the bindings unless
+/// [`LConfig::disable_native_codegen`] is set
+pub const LUAU_CODEGEN_HEADER: &str = "lute/extern/luau/CodeGen/include/luacodegen.h";
+
+/// Generates the bindings for `lcfg` into `OUT_DIR/lute_bindings.rs` and
+/// returns their path.
+///
+/// `extra_headers` (e.g. Lute runtime headers exposing `extern "C"`
+/// functions) are included after the Luau ones; only items whose name starts
+/// with `lua`/`luau`/`lute` (in any case) are kept.
+pub fn generate_bindings(lcfg: LConfig, extra_headers: &[&str]) -> PathBuf {
+    let lcfg = lcfg.for_target(&std::env::var("TARGET").unwrap());
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+    let mut headers = LUAU_HEADERS.to_vec();
+    if !lcfg.disable_native_codegen {
+        headers.push(LUAU_CODEGEN_HEADER);
+    }
+    headers.extend(extra_headers);
+
+    let mut wrapper = String::from("// Generated by lute-src-rs-common, do not edit\n");
+    for header in &headers {
+        rerun_if_changed(header);
+        let path = std::fs::canonicalize(header)
+            .unwrap_or_else(|e| panic!("Failed to find {header} (is the lute submodule checked out?): {e}"));
+        wrapper.push_str(&format!("#include \"{}\"\n", path.display()));
+    }
+    let wrapper_path = out_dir.join("lute_bindings.h");
+    std::fs::write(&wrapper_path, wrapper).expect("Failed to write the bindings header");
+
+    // The `extern "C"` API macros need the headers parsed as C++
+    let mut builder = bindgen::Builder::default()
+        .header(wrapper_path.display().to_string())
+        .clang_args(["-x", "c++", "-std=c++20"])
+        .allowlist_item("(?i)(lua|lute)\\w*")
+        .layout_tests(false);
+    for path in lute_cc_include_paths(&lcfg) {
+        builder = builder.clang_arg(format!("-I{}", path.display()));
+    }
+    for (name, value) in lute_cc_defines(&lcfg) {
+        builder = builder.clang_arg(format!("-D{name}={value}"));
+    }
+    if let Some(prefix) = lcfg.symbol_prefix {
+        builder = builder.parse_callbacks(Box::new(SymbolPrefix(prefix)));
+    }
+
+    let bindings_path = out_dir.join("lute_bindings.rs");
+    builder
+        .generate()
+        .unwrap_or_else(|e| panic!("Failed to generate the Luau/Lute bindings: {e}"))
+        .write_to_file(&bindings_path)
+        .unwrap_or_else(|e| panic!("Failed to write {}: {}", bindings_path.display(), e));
+
+    print_once(&format!("cargo:rustc-env=LUTE_BINDINGS={}", bindings_path.display()));
+    print_once(&format!("cargo:bindings={}", bindings_path.display()));
+    bindings_path
+}
+
+// Links the bindings against the prefixed symbols (see `prefix`)
+#[derive(Debug)]
+struct SymbolPrefix(&'static str);
+
+impl ParseCallbacks for SymbolPrefix {
+    fn generated_link_name_override(&self, item_info: ItemInfo<'_>) -> Option<String> {
+        match item_info.kind {
+            ItemKind::Function | ItemKind::Var => Some(format!("{}{}", self.0, item_info.name)),
+            _ => None,
+        }
+    }
+}
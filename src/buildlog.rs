@@ -0,0 +1,192 @@
+//! Capturing of CMake configure/build output into log files and extraction of
+//! the relevant compiler/linker errors from them on failure.
+//!
+//! In CI the actual compiler error of a failed Lute build is otherwise buried in
+//! thousands of lines of cargo output.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Maximum number of lines to show in a failure excerpt
+pub const MAX_EXCERPT_LINES: usize = 40;
+
+/// Runs `cmd`, writing both its stdout and stderr to `log_path` while still
+/// echoing them to the build script's own stdout/stderr.
+pub fn run_logged(cmd: &mut Command, log_path: &Path) -> io::Result<ExitStatus> {
+    let log = Arc::new(Mutex::new(File::create(log_path)?));
+    writeln!(log.lock().unwrap(), "running: {:?}", cmd)?;
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let out_log = log.clone();
+    let out_thread = thread::spawn(move || tee(stdout, &out_log, &mut io::stdout()));
+    let err_log = log.clone();
+    let err_thread = thread::spawn(move || tee(stderr, &err_log, &mut io::stderr()));
+
+    let status = child.wait()?;
+    let _ = out_thread.join();
+    let _ = err_thread.join();
+    log.lock().unwrap().flush()?;
+    Ok(status)
+}
+
+// Copies `src` line by line into the shared log file and `echo`
+fn tee<R: Read, W: Write>(src: R, log: &Mutex<File>, echo: &mut W) {
+    let mut reader = BufReader::new(src);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let _ = log.lock().unwrap().write_all(&line);
+                let _ = echo.write_all(&line);
+            }
+        }
+    }
+}
+
+/// Extracts the first compiler, linker and CMake errors from a build log.
+///
+/// Recognizes GCC/Clang (`file:line:col: error: ...`, `collect2: error`,
+/// `undefined reference to`), Apple ld (`Undefined symbols for architecture`),
+/// MSVC (`error C1234`, `error LNK2019`, `error MSB1234`) and CMake
+/// (`CMake Error at ...`, including its indented continuation lines) formats.
+pub fn error_excerpt(log: &str, max_lines: usize) -> Vec<String> {
+    let mut excerpt = Vec::new();
+    // Skip the `running: ...` header written by `run_logged`
+    let mut lines = log.lines().skip_while(|line| line.starts_with("running: ")).peekable();
+    while let Some(line) = lines.next() {
+        if excerpt.len() >= max_lines {
+            break;
+        }
+
+        if line.starts_with("CMake Error") {
+            // CMake errors span multiple lines, the message itself is indented
+            // and terminated by an empty line
+            excerpt.push(line.to_string());
+            while let Some(next) = lines.peek() {
+                if next.trim().is_empty() || excerpt.len() >= max_lines {
+                    break;
+                }
+                excerpt.push(next.to_string());
+                lines.next();
+            }
+            continue;
+        }
+
+        if line.starts_with("Undefined symbols for architecture") {
+            // Apple ld lists the missing symbols on the following indented lines
+            excerpt.push(line.to_string());
+            while let Some(next) = lines.peek() {
+                if !next.starts_with(' ') || excerpt.len() >= max_lines {
+                    break;
+                }
+                excerpt.push(next.to_string());
+                lines.next();
+            }
+            continue;
+        }
+
+        if is_error_line(line) {
+            excerpt.push(line.to_string());
+        }
+    }
+    excerpt
+}
+
+fn is_error_line(line: &str) -> bool {
+    // GCC, Clang, lld, gold, collect2 and friends
+    if line.contains(": error:")
+        || line.contains(": fatal error:")
+        || line.contains("undefined reference to")
+        || line.contains("multiple definition of")
+        || (line.starts_with("ld: ") && !line.starts_with("ld: warning"))
+    {
+        return true;
+    }
+
+    // MSVC compiler (C1234), linker (LNK1234) and MSBuild (MSB1234) errors
+    ["error C", "error LNK", "error MSB"]
+        .iter()
+        .any(|prefix| has_coded_error(line, prefix))
+}
+
+// Checks for `prefix` immediately followed by a 4 digit error code
+fn has_coded_error(line: &str, prefix: &str) -> bool {
+    line.match_indices(prefix).any(|(idx, _)| {
+        let code = &line[idx + prefix.len()..];
+        code.len() >= 4 && code.as_bytes()[..4].iter().all(u8::is_ascii_digit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excerpt_picks_compiler_and_linker_errors() {
+        let log = "running: \"cmake\" \"--build\" \".\"\n\
+                   [ 10%] Building CXX object VM/src/lapi.cpp.o\n\
+                   lute/vm/src/vm.cpp:12:5: error: use of undeclared identifier 'foo'\n\
+                   In file included from a.h:1:\n\
+                   /usr/bin/ld: Lute.Fs.a(fs.cpp.o): undefined reference to `uv_fs_open'\n\
+                   collect2: error: ld returned 1 exit status\n";
+        assert_eq!(
+            error_excerpt(log, MAX_EXCERPT_LINES),
+            [
+                "lute/vm/src/vm.cpp:12:5: error: use of undeclared identifier 'foo'",
+                "/usr/bin/ld: Lute.Fs.a(fs.cpp.o): undefined reference to `uv_fs_open'",
+                "collect2: error: ld returned 1 exit status",
+            ]
+        );
+    }
+
+    #[test]
+    fn excerpt_keeps_multi_line_errors() {
+        let log = "CMake Error at CMakeLists.txt:3 (add_subdirectory):\n  \
+                   The source directory\n\n    \
+                   lute/extern/luau\n\n\
+                   -- Configuring incomplete, errors occurred!\n\
+                   Undefined symbols for architecture arm64:\n  \
+                   \"_uv_loop_init\", referenced from:\n\
+                   ld: symbol(s) not found for architecture arm64\n";
+        assert_eq!(
+            error_excerpt(log, MAX_EXCERPT_LINES),
+            [
+                "CMake Error at CMakeLists.txt:3 (add_subdirectory):",
+                "  The source directory",
+                "Undefined symbols for architecture arm64:",
+                "  \"_uv_loop_init\", referenced from:",
+                "ld: symbol(s) not found for architecture arm64",
+            ]
+        );
+    }
+
+    #[test]
+    fn excerpt_is_limited() {
+        let log = "a.cpp:1:1: error: one\nb.cpp:1:1: error: two\nc.cpp:1:1: error: three\n";
+        assert_eq!(error_excerpt(log, 2).len(), 2);
+    }
+
+    #[test]
+    fn msvc_errors_need_a_code() {
+        assert!(is_error_line("lapi.cpp(12): error C2065: 'foo': undeclared identifier"));
+        assert!(is_error_line("Lute.Fs.lib(fs.obj) : error LNK2019: unresolved external symbol"));
+        assert!(is_error_line("C:\\msbuild\\x.targets(5,5): error MSB3073: exited with code 1"));
+        assert!(!is_error_line("-- Looking for error Codes"));
+        assert!(!is_error_line("ld: warning: object file was built for newer macOS"));
+        assert!(!is_error_line("compiling error_handler.cpp"));
+    }
+}
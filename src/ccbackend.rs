@@ -0,0 +1,326 @@
+//! Pure `cc` build backend for the Lute runtime ([`Backend::Cc`](crate::Backend::Cc)).
+//!
+//! Compiles Luau, the Lute modules and libuv directly through `cc::Build` into
+//! the same static libraries the CMake build produces, so that `finalize_build`
+//! links them unchanged. The set of libraries built is exactly the static part
+//! of `static_link_libraries` for the given `LConfig`.
+//!
+//! BoringSSL, libsodium, curl and friends are only buildable through CMake, so
+//! this backend requires `disable_net` and `disable_crypto`: it builds the
+//! runtime without the `@lute/net` and `@lute/crypto` modules, not the full
+//! runtime `Backend::CMake` can.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::commonflags::lute_cc_build;
+use crate::finalize::{LinkKind, static_link_libraries};
+use crate::rerun::rerun_if_changed;
+use crate::search::register_lib_dir;
+use crate::{LConfig, LinkMode, compdb, report, shared, toolchain, unity, wasi};
+
+/// How to compile one of the Luau/Lute static libraries
+struct CcLib {
+    /// Library name, matching the CMake target and the link list entry
+    name: &'static str,
+    /// Directories (relative to the crate root) whose `.cpp` files are compiled
+    dirs: &'static [&'static str],
+    /// Individual files (relative to the crate root), for libraries which only
+    /// use part of a directory
+    files: &'static [&'static str],
+}
+
+const CC_LIBS: &[CcLib] = &[
+    CcLib { name: "Luau.Ast", dirs: &["lute/extern/luau/Ast/src"], files: &[] },
+    CcLib { name: "Luau.Compiler", dirs: &["lute/extern/luau/Compiler/src"], files: &[] },
+    CcLib { name: "Luau.Config", dirs: &["lute/extern/luau/Config/src"], files: &[] },
+    CcLib { name: "Luau.Analysis", dirs: &["lute/extern/luau/Analysis/src"], files: &[] },
+    CcLib { name: "Luau.EqSat", dirs: &["lute/extern/luau/EqSat/src"], files: &[] },
+    CcLib { name: "Luau.VM", dirs: &["lute/extern/luau/VM/src"], files: &[] },
+    CcLib { name: "Luau.CodeGen", dirs: &["lute/extern/luau/CodeGen/src"], files: &[] },
+    CcLib {
+        name: "Luau.Require",
+        dirs: &["lute/extern/luau/Require/Runtime/src", "lute/extern/luau/Require/src"],
+        files: &[],
+    },
+    CcLib { name: "Luau.RequireNavigator", dirs: &["lute/extern/luau/Require/Navigator/src"], files: &[] },
+    CcLib {
+        name: "Luau.CLI.lib",
+        dirs: &[],
+        files: &[
+            "lute/extern/luau/CLI/src/AnalyzeRequirer.cpp",
+            "lute/extern/luau/CLI/src/Coverage.cpp",
+            "lute/extern/luau/CLI/src/FileUtils.cpp",
+            "lute/extern/luau/CLI/src/Flags.cpp",
+            "lute/extern/luau/CLI/src/Profiler.cpp",
+            "lute/extern/luau/CLI/src/ReplRequirer.cpp",
+            "lute/extern/luau/CLI/src/RequirerUtils.cpp",
+            "lute/extern/luau/CLI/src/VfsNavigator.cpp",
+        ],
+    },
+    CcLib { name: "Lute.Luau", dirs: &["lute/lute/luau/src"], files: &[] },
+    CcLib { name: "Lute.Fs", dirs: &["lute/lute/fs/src"], files: &[] },
+    CcLib { name: "Lute.Process", dirs: &["lute/lute/process/src"], files: &[] },
+    CcLib { name: "Lute.System", dirs: &["lute/lute/system/src"], files: &[] },
+    CcLib { name: "Lute.Task", dirs: &["lute/lute/task/src"], files: &[] },
+    CcLib { name: "Lute.Time", dirs: &["lute/lute/time/src"], files: &[] },
+    CcLib { name: "Lute.VM", dirs: &["lute/lute/vm/src"], files: &[] },
+    CcLib { name: "Lute.Require", dirs: &["lute/lute/require/src"], files: &[] },
+    CcLib { name: "Lute.Std", dirs: &["lute/lute/std/src"], files: &[] },
+    CcLib { name: "Lute.Runtime", dirs: &["lute/lute/runtime/src"], files: &[] },
+];
+
+/// Include directories needed on top of `LUTE_CC_INCLUDES` to compile the
+/// whole runtime
+const CC_BACKEND_INCLUDES: &[&str] = &[
+    "lute/extern/luau/Ast/include",
+    "lute/extern/luau/Analysis/include",
+    "lute/extern/luau/EqSat/include",
+    "lute/extern/luau/Config/include",
+    "lute/extern/luau/CodeGen/include",
+    "lute/extern/luau/Require/include",
+    "lute/extern/luau/Require/Runtime/include",
+    "lute/extern/luau/Require/Navigator/include",
+    "lute/extern/luau/CLI/include",
+    "lute/lute/require/include",
+    "lute/lute/std/include",
+];
+
+const LIBUV_COMMON_SOURCES: &[&str] = &[
+    "fs-poll.c",
+    "idna.c",
+    "inet.c",
+    "random.c",
+    "strscpy.c",
+    "strtok.c",
+    "thread-common.c",
+    "threadpool.c",
+    "timer.c",
+    "uv-common.c",
+    "uv-data-getter-setters.c",
+    "version.c",
+];
+
+const LIBUV_UNIX_SOURCES: &[&str] = &[
+    "unix/async.c",
+    "unix/core.c",
+    "unix/dl.c",
+    "unix/fs.c",
+    "unix/getaddrinfo.c",
+    "unix/getnameinfo.c",
+    "unix/loop-watcher.c",
+    "unix/loop.c",
+    "unix/pipe.c",
+    "unix/poll.c",
+    "unix/process.c",
+    "unix/random-devurandom.c",
+    "unix/signal.c",
+    "unix/stream.c",
+    "unix/tcp.c",
+    "unix/thread.c",
+    "unix/tty.c",
+    "unix/udp.c",
+];
+
+/// Builds every static library of the link list for `lcfg` through `cc`,
+/// returning the directory they were written to.
+///
+/// Unlike the CMake build, this also emits the `rustc-link-search` for that
+/// directory as all libraries end up in one place.
+pub fn build_lute_cc(lcfg: LConfig, static_crt: bool) -> PathBuf {
+    let lcfg = lcfg.for_target(&std::env::var("TARGET").unwrap());
+    if !lcfg.disable_net || !lcfg.disable_crypto {
+        panic!(
+            "The cc backend cannot build the net/crypto modules (BoringSSL, curl, libsodium), \
+             set disable_net and disable_crypto or use Backend::CMake"
+        );
+    }
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lute-cc");
+    std::fs::create_dir_all(&out_dir).expect("Failed to create cc backend output directory");
+    let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap();
+
+    for lib in static_link_libraries(lcfg, false) {
+        if lib.kind != LinkKind::Static {
+            continue;
+        }
+
+        let start = Instant::now();
+        let is_libuv = lib.name == "uv" || lib.name == "libuv";
+        let (mut build, files) = if is_libuv && target_os == "wasi" {
+            wasi::uv_stub_build()
+        } else if is_libuv {
+            libuv_build(&target_os, static_crt)
+        } else {
+            let cc_lib = CC_LIBS
+                .iter()
+                .find(|cc_lib| cc_lib.name == lib.name)
+                .unwrap_or_else(|| panic!("The cc backend does not know how to build {}", lib.name));
+
+            let mut files = collect_sources(cc_lib.dirs, "cpp");
+            files.extend(cc_lib.files.iter().map(|file| existing_source(file)));
+
+            let mut build = lute_cc_build(lcfg, static_crt);
+            build.includes(CC_BACKEND_INCLUDES);
+            (build, files)
+        };
+
+        if is_libuv {
+            // The Lute libraries get the toolchain through lute_cc_build
+            toolchain::configure_cc(&lcfg, &mut build, false);
+        }
+
+        if files.is_empty() {
+            panic!("No sources found for {}, is the lute submodule checked out?", lib.name);
+        }
+
+        for file in &files {
+            rerun_if_changed(file);
+        }
+
+        match lcfg.unity_build {
+            Some(unity) => build.files(unity::unity_sources(&lib.name, &files, &unity)),
+            None => build.files(&files),
+        };
+
+        build
+            .warnings(false)
+            .cargo_metadata(false) // finalize_build emits the link directives in order
+            .out_dir(&out_dir);
+
+        if lcfg.link_mode == LinkMode::Shared {
+            build.pic(true);
+        }
+
+        if lcfg.export_compile_commands {
+            compdb::add_cc_build(&build, &files);
+        }
+
+        build.compile(&lib.name);
+        report::record_phase(&format!("compile {}", lib.name), start.elapsed());
+    }
+
+    if lcfg.export_compile_commands {
+        compdb::write(&lcfg);
+    }
+
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    register_lib_dir(&out_dir);
+    if lcfg.link_mode == LinkMode::Shared {
+        shared::link_shared_runtime(lcfg);
+    }
+    out_dir
+}
+
+/// Returns the C build and sources of libuv for `target_os`
+fn libuv_build(target_os: &str, static_crt: bool) -> (cc::Build, Vec<String>) {
+    let mut build = cc::Build::new();
+    build
+        .cpp(false)
+        .include("lute/extern/libuv/include")
+        .include("lute/extern/libuv/src")
+        .static_crt(static_crt);
+
+    let mut sources = LIBUV_COMMON_SOURCES.to_vec();
+    match target_os {
+        "windows" => {
+            build
+                .define("WIN32_LEAN_AND_MEAN", None)
+                .define("_WIN32_WINNT", "0x0602")
+                .define("_CRT_DECLARE_NONSTDC_NAMES", "0");
+            let mut files = prefixed_libuv_sources(&sources);
+            files.extend(collect_sources(&["lute/extern/libuv/src/win"], "c"));
+            return (build, files);
+        }
+        "linux" | "android" => {
+            build
+                .define("_GNU_SOURCE", None)
+                .define("_FILE_OFFSET_BITS", "64")
+                .define("_LARGEFILE_SOURCE", None);
+            sources.extend(LIBUV_UNIX_SOURCES);
+            sources.extend([
+                "unix/linux.c",
+                "unix/procfs-exepath.c",
+                "unix/proctitle.c",
+                "unix/random-getrandom.c",
+                "unix/random-sysctl-linux.c",
+            ]);
+            if target_os == "android" {
+                sources.push("unix/random-getentropy.c");
+            }
+        }
+        "macos" | "ios" | "tvos" | "watchos" | "visionos" => {
+            build
+                .define("_DARWIN_UNLIMITED_SELECT", "1")
+                .define("_DARWIN_USE_64_BIT_INODE", "1")
+                .define("_FILE_OFFSET_BITS", "64")
+                .define("_LARGEFILE_SOURCE", None);
+            sources.extend(LIBUV_UNIX_SOURCES);
+            sources.extend([
+                "unix/bsd-ifaddrs.c",
+                "unix/darwin-proctitle.c",
+                "unix/darwin.c",
+                "unix/fsevents.c",
+                "unix/kqueue.c",
+                "unix/proctitle.c",
+                "unix/random-getentropy.c",
+            ]);
+        }
+        "freebsd" | "dragonfly" | "openbsd" | "netbsd" => {
+            build
+                .define("_FILE_OFFSET_BITS", "64")
+                .define("_LARGEFILE_SOURCE", None);
+            sources.extend(LIBUV_UNIX_SOURCES);
+            sources.extend([
+                "unix/bsd-ifaddrs.c",
+                "unix/bsd-proctitle.c",
+                "unix/kqueue.c",
+                "unix/posix-hrtime.c",
+            ]);
+            match target_os {
+                "openbsd" => sources.extend(["unix/openbsd.c", "unix/random-getentropy.c"]),
+                "netbsd" => sources.push("unix/netbsd.c"),
+                _ => sources.extend(["unix/freebsd.c", "unix/random-getrandom.c"]),
+            }
+        }
+        other => panic!("The cc backend does not know how to build libuv for {other}"),
+    }
+
+    (build, prefixed_libuv_sources(&sources))
+}
+
+// Resolves libuv sources relative to `lute/extern/libuv/src`
+fn prefixed_libuv_sources(sources: &[&str]) -> Vec<String> {
+    sources
+        .iter()
+        .map(|source| existing_source(&format!("lute/extern/libuv/src/{source}")))
+        .collect()
+}
+
+// Panics if the source `file` listed above doesn't exist (the lute submodule
+// isn't checked out or upstream renamed it), rather than leaving its symbols
+// undefined at link time
+fn existing_source(file: &str) -> String {
+    if !Path::new(file).is_file() {
+        panic!("The cc backend source {file} does not exist, is the lute submodule checked out (and up to date)?");
+    }
+    file.to_string()
+}
+
+/// Returns all files with extension `ext` directly inside `dirs`, sorted
+fn collect_sources(dirs: &[&str], ext: &str) -> Vec<String> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() && path.extension().is_some_and(|e| e == ext) {
+                files.push(path.display().to_string());
+            }
+        }
+    }
+    files.sort();
+    files
+}
@@ -0,0 +1,92 @@
+//! The host `lute` CLI ([`LConfig::build_cli`]).
+//!
+//! Build scripts and integration tests of the crates embedding Lute can run
+//! Luau scripts with the very engine revision and module switches they link.
+//! The CLI must run on the build machine: when the target is the host and the
+//! runtime is built with CMake, its target is added to that build; otherwise
+//! (cross compiling or the `cc` backend) the lute project is configured a
+//! second time in `OUT_DIR/lute-cli`, for the host with its default compilers
+//! and none of the target specific settings (toolchain, CRT, system
+//! dependencies, ...).
+//!
+//! The executable's path is exported as the `LUTE_CLI` environment variable
+//! of the crate being built (for `env!("LUTE_CLI")` in its tests) and as the
+//! `cli` [cargo metadata](crate#metadata-for-dependents) key, which the build
+//! scripts of dependents read as `DEP_LUTE_CLI`:
+//!
+//! ```no_run
+//! let lute = std::env::var("DEP_LUTE_CLI").expect("the lute crate has `links = \"lute\"`");
+//! std::process::Command::new(lute).arg("run").arg("codegen.luau").status().unwrap();
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::cmake::Config;
+use crate::commonflags::define_lute_options;
+use crate::rerun::{print_once, rerun_if_changed};
+use crate::{Backend, LConfig, wasm};
+
+/// CMake target of the `lute` executable
+pub const CLI_TARGET: &str = "Lute.CLI";
+
+/// Returns true if the CLI can be built by the CMake build of the runtime
+/// itself (the target runs on the host)
+pub(crate) fn shares_target_build(lcfg: &LConfig) -> bool {
+    let target = std::env::var("TARGET").unwrap();
+    lcfg.backend == Backend::CMake && std::env::var("HOST").is_ok_and(|host| host == target)
+}
+
+/// Builds the `lute` CLI for the host with the module switches of `lcfg` in
+/// `OUT_DIR/lute-cli` and exports its path, which is returned
+pub fn build_host_cli(lcfg: &LConfig) -> PathBuf {
+    rerun_if_changed("lute");
+
+    let host = std::env::var("HOST").unwrap();
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lute-cli");
+
+    let mut cmake = Config::new("lute");
+    define_lute_options(&mut cmake, lcfg);
+    cmake
+        .target(&host)
+        .host(&host)
+        .out_dir(&out_dir)
+        .profile("Release")
+        .cxxflag(wasm::exceptions_flag(lcfg, &host))
+        .build_target(CLI_TARGET)
+        .always_configure(false);
+    let dst = cmake.build();
+
+    export_cli(&dst.join("build"))
+}
+
+/// Locates the `lute` executable in the CMake build directory `build_dir`
+/// and exports its path
+pub(crate) fn export_cli(build_dir: &Path) -> PathBuf {
+    let host = std::env::var("HOST").unwrap();
+    let file_name = if host.contains("windows") { "lute.exe" } else { "lute" };
+    let cli = find_executable(build_dir, file_name).unwrap_or_else(|| {
+        panic!("The {} build did not produce {} in {}", CLI_TARGET, file_name, build_dir.display())
+    });
+
+    print_once(&format!("cargo:rustc-env=LUTE_CLI={}", cli.display()));
+    print_once(&format!("cargo:cli={}", cli.display()));
+    cli
+}
+
+fn find_executable(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_file() && entry.file_name() == file_name {
+            return Some(path);
+        }
+        // The object directories can't hold it
+        if file_type.is_dir() && path.extension().is_none_or(|ext| ext != "dir") {
+            subdirs.push(path);
+        }
+    }
+    subdirs.into_iter().find_map(|subdir| find_executable(&subdir, file_name))
+}
@@ -21,7 +21,7 @@
 //! ## Examples
 //!
 //! ```no_run
-//! use cmake;
+//! use lute_src_rs_common::cmake;
 //!
 //! // Builds the project in the directory located in `libfoo`, installing it
 //! // into $OUT_DIR
@@ -32,7 +32,7 @@
 //! ```
 //!
 //! ```no_run
-//! use cmake::Config;
+//! use lute_src_rs_common::cmake::Config;
 //!
 //! let dst = Config::new("libfoo")
 //!                  .define("FOO", "BAR")
@@ -54,6 +54,20 @@ use std::io::prelude::*;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::buildlog::{error_excerpt, run_logged, MAX_EXCERPT_LINES};
+use crate::rerun::{rerun_if_changed, rerun_if_env_changed};
+
+/// Name of the file (next to `CMakeCache.txt`) recording the inputs of the
+/// last successful configure step.
+const CONFIGURE_FINGERPRINT_FILE: &str = "lute-configure-fingerprint.txt";
+
+/// Name of the file (in the output directory) capturing the configure step output.
+pub const CONFIGURE_LOG_FILE: &str = "lute-cmake-configure.log";
+
+/// Name of the file (in the output directory) capturing the build step output.
+pub const BUILD_LOG_FILE: &str = "lute-cmake-build.log";
 
 /// Builder style configuration for a pending CMake build.
 pub struct Config {
@@ -72,10 +86,12 @@ pub struct Config {
     configure_args: Vec<OsString>,
     build_args: Vec<OsString>,
     cmake_target: Option<String>,
+    cmake_targets: Vec<String>, // lute-src-rs patch: multiple build targets
     env: Vec<(OsString, OsString)>,
     static_crt: Option<bool>,
     uses_cxx11: bool,
     always_configure: bool,
+    generate_toolchain_file: bool, // lute-src-rs patch
     no_build_target: bool,
     no_default_flags: bool,
     verbose_cmake: bool,
@@ -84,6 +100,7 @@ pub struct Config {
     c_cfg: Option<cc::Build>,
     cxx_cfg: Option<cc::Build>,
     env_cache: HashMap<String, Option<OsString>>,
+    timings: Vec<(&'static str, Duration)>,
 }
 
 /// Builds the native library rooted at `path` with the default cmake options.
@@ -92,7 +109,7 @@ pub struct Config {
 /// # Examples
 ///
 /// ```no_run
-/// use cmake;
+/// use lute_src_rs_common::cmake;
 ///
 /// // Builds the project in the directory located in `libfoo`, installing it
 /// // into $OUT_DIR
@@ -198,10 +215,12 @@ impl Config {
             configure_args: Vec::new(),
             build_args: Vec::new(),
             cmake_target: None,
+            cmake_targets: Vec::new(),
             env: Vec::new(),
             static_crt: None,
             uses_cxx11: false,
             always_configure: true,
+            generate_toolchain_file: false,
             no_build_target: false,
             verbose_cmake: false,
             verbose_make: false,
@@ -209,6 +228,7 @@ impl Config {
             c_cfg: None,
             cxx_cfg: None,
             env_cache: HashMap::new(),
+            timings: Vec::new(),
         }
     }
 
@@ -381,6 +401,19 @@ impl Config {
         self
     }
 
+    /// Sets several build targets for the final `cmake` build step (lute-src-rs
+    /// patch). Overrides [`build_target`][Self::build_target].
+    ///
+    /// Requires CMake 3.15, older versions build the default target instead.
+    pub fn build_targets<I, S>(&mut self, targets: I) -> &mut Config
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.cmake_targets = targets.into_iter().map(|t| t.as_ref().to_string()).collect();
+        self
+    }
+
     /// Alters the default target triple on OSX to ensure that c++11 is
     /// available. Does not change the target triple if it is explicitly
     /// specified.
@@ -397,11 +430,29 @@ impl Config {
     ///
     /// In some cases, when you have a big project, you can disable
     /// subsequents runs of cmake to make `cargo build` faster.
+    ///
+    /// When disabled, the configure step is still rerun if any of its inputs
+    /// (defines, flags, compilers, environment or the contents of the
+    /// toolchain file) changed since the last configure.
     pub fn always_configure(&mut self, always_configure: bool) -> &mut Config {
         self.always_configure = always_configure;
         self
     }
 
+    /// When cross compiling without a `CMAKE_TOOLCHAIN_FILE`, generate one from
+    /// the compilers, archiver and ranlib `cc` picks for the target, the
+    /// compiler's sysroot (or `CMAKE_SYSROOT`) and `CMAKE_FIND_ROOT_PATH_MODE_*`
+    /// settings keeping `find_*` calls away from host libraries (lute-src-rs
+    /// patch). Without a known sysroot (e.g. clang, which doesn't print one)
+    /// the `find_*` calls are left unrestricted, set `CMAKE_SYSROOT` for the
+    /// target to get them. Not done for MSVC and Android NDK targets.
+    ///
+    /// This option defaults to `false`.
+    pub fn generate_toolchain_file(&mut self, generate: bool) -> &mut Config {
+        self.generate_toolchain_file = generate;
+        self
+    }
+
     /// Sets very verbose output.
     pub fn very_verbose(&mut self, value: bool) -> &mut Config {
         self.verbose_cmake = value;
@@ -458,55 +509,22 @@ impl Config {
                 // Set CMAKE_SYSTEM_NAME and CMAKE_SYSTEM_PROCESSOR when cross compiling
                 let os = getenv_unwrap("CARGO_CFG_TARGET_OS");
                 let arch = getenv_unwrap("CARGO_CFG_TARGET_ARCH");
-                // CMAKE_SYSTEM_NAME list
-                // https://gitlab.kitware.com/cmake/cmake/-/issues/21489#note_1077167
-                //
-                // CMAKE_SYSTEM_PROCESSOR
-                // some of the values come from https://en.wikipedia.org/wiki/Uname
-                let (system_name, system_processor) = match (os.as_str(), arch.as_str()) {
-                    ("android", "arm") => ("Android", "armv7-a"),
-                    ("android", "x86") => ("Android", "i686"),
-                    ("android", arch) => ("Android", arch),
-                    ("dragonfly", arch) => ("DragonFly", arch),
-                    ("macos", "aarch64") => ("Darwin", "arm64"),
-                    ("macos", arch) => ("Darwin", arch),
-                    ("freebsd", "x86_64") => ("FreeBSD", "amd64"),
-                    ("freebsd", arch) => ("FreeBSD", arch),
-                    ("fuchsia", arch) => ("Fuchsia", arch),
-                    ("haiku", arch) => ("Haiku", arch),
-                    ("ios", "aarch64") => ("iOS", "arm64"),
-                    ("ios", arch) => ("iOS", arch),
-                    ("linux", arch) => {
-                        let name = "Linux";
-                        match arch {
-                            "powerpc" => (name, "ppc"),
-                            "powerpc64" => (name, "ppc64"),
-                            "powerpc64le" => (name, "ppc64le"),
-                            _ => (name, arch),
-                        }
-                    }
-                    ("netbsd", arch) => ("NetBSD", arch),
-                    ("openbsd", "x86_64") => ("OpenBSD", "amd64"),
-                    ("openbsd", arch) => ("OpenBSD", arch),
-                    ("solaris", arch) => ("SunOS", arch),
-                    ("tvos", "aarch64") => ("tvOS", "arm64"),
-                    ("tvos", arch) => ("tvOS", arch),
-                    ("visionos", "aarch64") => ("visionOS", "arm64"),
-                    ("visionos", arch) => ("visionOS", arch),
-                    ("watchos", "aarch64") => ("watchOS", "arm64"),
-                    ("watchos", arch) => ("watchOS", arch),
-                    ("windows", "x86_64") => ("Windows", "AMD64"),
-                    ("windows", "x86") => ("Windows", "X86"),
-                    ("windows", "aarch64") => ("Windows", "ARM64"),
-                    ("none", arch) => ("Generic", arch),
-                    // Others
-                    (os, arch) => (os, arch),
-                };
+                let (system_name, system_processor) = cmake_system(&os, &arch);
                 self.define("CMAKE_SYSTEM_NAME", system_name);
                 self.define("CMAKE_SYSTEM_PROCESSOR", system_processor);
             }
         }
 
+        // The toolchain file is an input of the configure step as much as the
+        // defines are, so track its contents too.
+        if let Some((_, toolchain_file)) = self
+            .defines
+            .iter()
+            .find(|(k, _)| k == "CMAKE_TOOLCHAIN_FILE")
+        {
+            rerun_if_changed(Path::new(toolchain_file));
+        }
+
         let generator = self
             .generator
             .clone()
@@ -569,7 +587,9 @@ impl Config {
         let mut cmake_prefix_path = Vec::new();
         for dep in &self.deps {
             let dep = dep.to_uppercase().replace('-', "_");
-            if let Some(root) = env::var_os(format!("DEP_{}_ROOT", dep)) {
+            let var = format!("DEP_{}_ROOT", dep);
+            rerun_if_env_changed(&var);
+            if let Some(root) = env::var_os(var) {
                 cmake_prefix_path.push(PathBuf::from(root));
             }
         }
@@ -577,6 +597,24 @@ impl Config {
             .getenv_target_os("CMAKE_PREFIX_PATH")
             .unwrap_or_default();
         cmake_prefix_path.extend(env::split_paths(&system_prefix));
+
+        // lute-src-rs patch: generated toolchain file for cross builds
+        if self.generate_toolchain_file
+            && target != host
+            && !ndk
+            && !target.contains("msvc")
+            && !self.defined("CMAKE_TOOLCHAIN_FILE")
+        {
+            let toolchain_file = self.write_toolchain_file(
+                &dst,
+                &c_cfg,
+                &c_compiler,
+                &cxx_compiler,
+                &cmake_prefix_path,
+            );
+            self.define("CMAKE_TOOLCHAIN_FILE", toolchain_file);
+        }
+
         let cmake_prefix_path = env::join_paths(&cmake_prefix_path).unwrap();
 
         // Build up the first cmake command to build the build system.
@@ -823,9 +861,28 @@ impl Config {
             cmd.env(k, v);
         }
 
-        if self.always_configure || !build.join("CMakeCache.txt").exists() {
-            cmd.args(&self.configure_args);
-            run(cmd.env("CMAKE_PREFIX_PATH", cmake_prefix_path), "cmake");
+        cmd.args(&self.configure_args);
+        cmd.env("CMAKE_PREFIX_PATH", cmake_prefix_path);
+
+        // lute-src-rs patch: only skip the configure step if the inputs of the
+        // previous configure (recorded next to CMakeCache.txt) are unchanged
+        let fingerprint_path = build.join(CONFIGURE_FINGERPRINT_FILE);
+        let fingerprint = self.configure_fingerprint(&cmd);
+        let up_to_date = build.join("CMakeCache.txt").exists()
+            && fs::read_to_string(&fingerprint_path).is_ok_and(|old| old == fingerprint);
+        if self.always_configure || !up_to_date {
+            // Don't leave a stale fingerprint behind if the configure step fails
+            let _ = fs::remove_file(&fingerprint_path);
+            let start = Instant::now();
+            run(&mut cmd, "cmake", &dst.join(CONFIGURE_LOG_FILE));
+            self.timings.push(("configure", start.elapsed()));
+            if let Err(e) = fs::write(&fingerprint_path, &fingerprint) {
+                println!(
+                    "failed to write configure fingerprint {}: {}",
+                    fingerprint_path.display(),
+                    e
+                );
+            }
         } else {
             println!("CMake project was already configured. Skipping configuration step.");
         }
@@ -869,7 +926,12 @@ impl Config {
         println!("Running CMake build in {}", build.display());
         cmd.arg("--build").arg(".").arg("-j").arg("4"); // lute-src-rs patch: use . as build dir and jobs as 4
 
-        if !self.no_build_target {
+        if !self.cmake_targets.is_empty() {
+            // lute-src-rs patch: multiple targets
+            if version >= Version::new(3, 15) {
+                cmd.arg("--target").args(&self.cmake_targets);
+            }
+        } else if !self.no_build_target {
             let target = self
                 .cmake_target
                 .clone()
@@ -892,12 +954,110 @@ impl Config {
             cmd.arg("--").args(&self.build_args);
         }
 
-        run(&mut cmd, "cmake");
+        let start = Instant::now();
+        run(&mut cmd, "cmake", &dst.join(BUILD_LOG_FILE));
+        self.timings.push(("build", start.elapsed()));
 
         println!("cargo:root={}", dst.display());
         dst
     }
 
+    // lute-src-rs patch: see `generate_toolchain_file`
+    fn write_toolchain_file(
+        &mut self,
+        dst: &Path,
+        c_cfg: &cc::Build,
+        c_compiler: &cc::Tool,
+        cxx_compiler: &cc::Tool,
+        prefix_path: &[PathBuf],
+    ) -> PathBuf {
+        let defined = |var: &str| {
+            self.defines
+                .iter()
+                .find(|(k, _)| k == var)
+                .map(|(_, v)| v.to_string_lossy().into_owned())
+        };
+        let system = defined("CMAKE_SYSTEM_NAME").map(|name| {
+            (name, defined("CMAKE_SYSTEM_PROCESSOR").unwrap_or_default())
+        });
+
+        let sysroot = self
+            .getenv_target_os("CMAKE_SYSROOT")
+            .map(PathBuf::from)
+            .or_else(|| {
+                // GCC cross compilers know their sysroot, clang prints nothing
+                let output = c_compiler.to_command().arg("-print-sysroot").output().ok()?;
+                let sysroot = String::from_utf8(output.stdout).ok()?;
+                let sysroot = PathBuf::from(sysroot.trim());
+                (output.status.success() && sysroot.is_dir()).then_some(sysroot)
+            });
+
+        // Restricting the searches to the find roots without a sysroot among
+        // them would hide the compiler's own libraries and headers, so CMake's
+        // defaults are kept then
+        let find_root_path = sysroot.as_ref().map(|sysroot| {
+            let mut find_root_path = vec![sysroot.clone()];
+            find_root_path.extend(prefix_path.iter().cloned());
+            find_root_path
+        });
+
+        let toolchain_file = crate::toolchain::ToolchainFile {
+            system,
+            c_compiler: Some(find_exe(c_compiler.path())),
+            cxx_compiler: Some(find_exe(cxx_compiler.path())),
+            ar: c_cfg
+                .try_get_archiver()
+                .ok()
+                .map(|ar| find_exe(Path::new(ar.get_program()))),
+            ranlib: c_cfg
+                .try_get_ranlib()
+                .ok()
+                .map(|ranlib| find_exe(Path::new(ranlib.get_program()))),
+            sysroot,
+            find_root_path,
+        };
+
+        let path = dst.join("lute-toolchain.cmake");
+        let _ = fs::create_dir_all(dst);
+        toolchain_file.write(&path);
+        path
+    }
+
+    /// Returns how long each step (`configure`, `build`) run by
+    /// [`build`][Self::build] took. A skipped configure step isn't listed.
+    pub fn timings(&self) -> &[(&'static str, Duration)] {
+        &self.timings
+    }
+
+    // Renders everything that influences the configure step (the command line,
+    // its environment and the contents of the toolchain file) so that it can
+    // be compared against the previous run.
+    fn configure_fingerprint(&self, cmd: &Command) -> String {
+        let mut fingerprint = String::new();
+        fingerprint.push_str(&format!("program: {:?}\n", cmd.get_program()));
+        for arg in cmd.get_args() {
+            fingerprint.push_str(&format!("arg: {:?}\n", arg));
+        }
+        let mut envs = cmd.get_envs().collect::<Vec<_>>();
+        envs.sort();
+        for (k, v) in envs {
+            fingerprint.push_str(&format!("env: {:?}={:?}\n", k, v));
+        }
+        if let Some((_, toolchain_file)) = self
+            .defines
+            .iter()
+            .find(|(k, _)| k == "CMAKE_TOOLCHAIN_FILE")
+        {
+            let contents = fs::read(toolchain_file).unwrap_or_default();
+            fingerprint.push_str(&format!(
+                "toolchain: {:?}\n{}\n",
+                toolchain_file,
+                String::from_utf8_lossy(&contents)
+            ));
+        }
+        fingerprint
+    }
+
     fn cmake_executable(&mut self) -> OsString {
         self.getenv_target_os("CMAKE")
             .unwrap_or_else(|| OsString::from("cmake"))
@@ -939,6 +1099,7 @@ impl Config {
         }
         let r = env::var_os(v);
         println!("{} = {:?}", v, r);
+        rerun_if_env_changed(v);
         self.env_cache.insert(v.to_string(), r.clone());
         r
     }
@@ -1083,9 +1244,62 @@ impl Default for Version {
     }
 }
 
-fn run(cmd: &mut Command, program: &str) {
+/// Returns the `CMAKE_SYSTEM_NAME` and `CMAKE_SYSTEM_PROCESSOR` for a cargo
+/// target os and arch (lute-src-rs patch: shared with generated toolchain files)
+pub fn cmake_system<'a>(os: &'a str, arch: &'a str) -> (&'a str, &'a str) {
+    // CMAKE_SYSTEM_NAME list
+    // https://gitlab.kitware.com/cmake/cmake/-/issues/21489#note_1077167
+    //
+    // CMAKE_SYSTEM_PROCESSOR
+    // some of the values come from https://en.wikipedia.org/wiki/Uname
+    match (os, arch) {
+        ("android", "arm") => ("Android", "armv7-a"),
+        ("android", "x86") => ("Android", "i686"),
+        ("android", arch) => ("Android", arch),
+        ("dragonfly", arch) => ("DragonFly", arch),
+        ("macos", "aarch64") => ("Darwin", "arm64"),
+        ("macos", arch) => ("Darwin", arch),
+        ("freebsd", "x86_64") => ("FreeBSD", "amd64"),
+        ("freebsd", arch) => ("FreeBSD", arch),
+        ("fuchsia", arch) => ("Fuchsia", arch),
+        ("haiku", arch) => ("Haiku", arch),
+        ("ios", "aarch64") => ("iOS", "arm64"),
+        ("ios", arch) => ("iOS", arch),
+        ("linux", arch) => {
+            let name = "Linux";
+            match arch {
+                "powerpc" => (name, "ppc"),
+                "powerpc64" => (name, "ppc64"),
+                "powerpc64le" => (name, "ppc64le"),
+                _ => (name, arch),
+            }
+        }
+        ("netbsd", arch) => ("NetBSD", arch),
+        ("openbsd", "x86_64") => ("OpenBSD", "amd64"),
+        ("openbsd", arch) => ("OpenBSD", arch),
+        ("solaris", arch) => ("SunOS", arch),
+        ("tvos", "aarch64") => ("tvOS", "arm64"),
+        ("tvos", arch) => ("tvOS", arch),
+        ("visionos", "aarch64") => ("visionOS", "arm64"),
+        ("visionos", arch) => ("visionOS", arch),
+        ("watchos", "aarch64") => ("watchOS", "arm64"),
+        ("watchos", arch) => ("watchOS", arch),
+        ("windows", "x86_64") => ("Windows", "AMD64"),
+        ("windows", "x86") => ("Windows", "X86"),
+        ("windows", "aarch64") => ("Windows", "ARM64"),
+        ("none", arch) => ("Generic", arch),
+        // Others
+        (os, arch) => (os, arch),
+    }
+}
+
+// lute-src-rs patch: the output of the command is captured into `log_path`
+// (and still echoed), failures report the log path and the first errors found
+// in it.
+fn run(cmd: &mut Command, program: &str, log_path: &Path) {
     println!("running: {:?}", cmd);
-    let status = match cmd.status() {
+    println!("logging to: {}", log_path.display());
+    let status = match run_logged(cmd, log_path) {
         Ok(status) => status,
         Err(ref e) if e.kind() == ErrorKind::NotFound => {
             fail(&format!(
@@ -1102,9 +1316,19 @@ fn run(cmd: &mut Command, program: &str) {
                 status, program
             ));
         }
+
+        let log = fs::read(log_path).unwrap_or_default();
+        let excerpt = error_excerpt(&String::from_utf8_lossy(&log), MAX_EXCERPT_LINES);
+        let excerpt = if excerpt.is_empty() {
+            "(no compiler, linker or CMake errors recognized, see the full log)".to_string()
+        } else {
+            excerpt.join("\n")
+        };
         fail(&format!(
-            "command did not execute successfully, got: {}",
-            status
+            "command did not execute successfully, got: {}\n\nfull log: {}\n\nfirst errors:\n{}",
+            status,
+            log_path.display(),
+            excerpt
         ));
     }
 }
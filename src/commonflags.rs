@@ -1,22 +1,52 @@
-use crate::{LConfig, cmake::Config};
+use crate::ccbackend::build_lute_cc;
+use crate::cmake::Config;
+use crate::rerun::rerun_if_changed;
+use crate::search::register_lib_dir;
+use crate::{Backend, CppStdlib, DepSource, LConfig, LinkMode, Toolchain};
+use crate::{archive, cli, compdb, finalize, report, shared, toolchain, unity, wasi, wasm};
+use crate::finalize::cmake_build_targets;
+use crate::sysdeps;
 
+/// Builds the Lute runtime from source with the backend selected by
+/// [`LConfig::backend`], returning the output directory of the build.
+pub fn build_lute_from_source(lcfg: LConfig, is_prebuilt: bool) -> std::path::PathBuf {
+    let lcfg = lcfg.for_target(&std::env::var("TARGET").unwrap());
+    match lcfg.backend {
+        Backend::CMake => build_lute_cmake(lcfg, is_prebuilt),
+        Backend::Cc => {
+            if lcfg.build_cli {
+                cli::build_host_cli(&lcfg);
+            }
+            let static_crt = finalize::static_crt(&lcfg, is_prebuilt);
+            let dst = build_lute_cc(lcfg, static_crt);
+            report::record_lconfig(&lcfg);
+            report::record_source("cc");
+            report::record_crt(static_crt);
+            report::write();
+            dst
+        }
+    }
+}
+
+/// Builds the Lute runtime from source, the same as [`build_lute_from_source`]
+/// which it predates: despite its name, [`Backend::Cc`] builds (which WASI
+/// targets always use) don't go through CMake.
 pub fn setup_lute_cmake(lcfg: LConfig, is_prebuilt: bool) -> std::path::PathBuf {
-    let static_crt = if is_prebuilt {
-        // Prebuilt lute runtime uses dynamic CRT
-        false
-    } else {
-        // Build lute runtime from source, use static CRT
-        true
-    };
+    build_lute_from_source(lcfg, is_prebuilt)
+}
 
+fn build_lute_cmake(lcfg: LConfig, is_prebuilt: bool) -> std::path::PathBuf {
     let mut config = cc::Build::new();
 
     let target = std::env::var("TARGET").unwrap();
+    let lcfg = lcfg.for_target(&target);
+    // Prebuilt lute runtimes use the dynamic CRT, see LConfig::crt otherwise
+    let static_crt = finalize::static_crt(&lcfg, is_prebuilt);
 
-    if target.ends_with("emscripten") {
-        // Enable c++ exceptions for emscripten (it's disabled by default)
-        // Later we should switch to wasm exceptions
-        config.flag_if_supported("-fexceptions");
+    if wasm::is_emscripten(&target) {
+        // Enable c++ exceptions for emscripten (it's disabled by default), JS
+        // or native wasm exceptions depending on lcfg.emscripten
+        config.flag_if_supported(wasm::exceptions_flag(&lcfg, &target));
     }
 
     config
@@ -25,12 +55,102 @@ pub fn setup_lute_cmake(lcfg: LConfig, is_prebuilt: bool) -> std::path::PathBuf
         .std("c++20")
         .cpp(true)
         .static_crt(static_crt);
+    if lcfg.cpp_stdlib == CppStdlib::LibCxx {
+        config.flag_if_supported("-stdlib=libc++");
+    }
+    toolchain::configure_cc(&lcfg, &mut config, true);
+
+    // The whole lute tree (including the vendored luau/libuv/etc. sources) is
+    // an input of the CMake build
+    rerun_if_changed("lute");
 
-    Config::new("lute")
+    // Cross builds configure the project for the host as well, before the
+    // target build so that its `cargo:root` is the one dependents see
+    let cli_in_target_build = lcfg.build_cli && cli::shares_target_build(&lcfg);
+    if lcfg.build_cli && !cli_in_target_build {
+        cli::build_host_cli(&lcfg);
+    }
+    let mut build_targets = cmake_build_targets(lcfg);
+    if cli_in_target_build {
+        build_targets.push(cli::CLI_TARGET.to_string());
+    }
+
+    let mut cmake = Config::new("lute");
+    define_lute_options(&mut cmake, &lcfg);
+    cmake
         .profile("Release") // Debug builds tend to be extremely slow and nearly unusable in practice
-        .define("LUAU_EXTERN_C", "ON") // Provides DLUA_USE_LONGJMP, DLUA_API, LUACODE_API, LUACODEGEN_API
         .define("LUAU_STATIC_CRT", if static_crt { "ON" } else { "OFF" }) 
         //.define("CMAKE_MSVC_RUNTIME_LIBRARY", "MultiThreaded$<$<CONFIG:Debug>:Debug>") // Use static CRT for MSVC
+        .cxxflag(
+            wasm::exceptions_flag(&lcfg, &target) // Enable C++ exceptions on non-Windows
+        )
+        .init_cxx_cfg(config)
+        .build_targets(build_targets) // Only what the link list needs (and the CLI)
+        .always_configure(false) // Only reconfigure when the configure inputs change
+        .generate_toolchain_file(true) // Complete toolchain file when cross compiling
+        .static_crt(static_crt);
+
+    // The C compiler matters for zig and musl cross builds
+    let mut c_config = cc::Build::new();
+    c_config.warnings(false).static_crt(static_crt);
+    toolchain::configure_cc(&lcfg, &mut c_config, false);
+    cmake.init_c_cfg(c_config);
+    if lcfg.toolchain == Toolchain::Zig {
+        cmake.define("CMAKE_TOOLCHAIN_FILE", toolchain::zig_toolchain(&target).toolchain_file);
+    }
+
+    if lcfg.export_compile_commands {
+        cmake.define("CMAKE_EXPORT_COMPILE_COMMANDS", "ON");
+    }
+
+    if lcfg.link_mode == LinkMode::Shared {
+        // The static libraries get linked into a shared library afterwards
+        cmake
+            .pic(true)
+            .define("CMAKE_POSITION_INDEPENDENT_CODE", "ON");
+    }
+
+    if let Some(unity) = lcfg.unity_build {
+        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+        cmake
+            .define("CMAKE_UNITY_BUILD", "ON")
+            .define("CMAKE_UNITY_BUILD_BATCH_SIZE", unity.batch_size.to_string())
+            .define("CMAKE_PROJECT_INCLUDE", unity::write_cmake_exclude_script(&out_dir, &unity));
+    }
+
+    let dst = cmake.build();
+
+    if lcfg.export_compile_commands {
+        compdb::add_cmake_build(&dst.join("build"));
+        compdb::write(&lcfg);
+    }
+
+    register_lib_dir(&dst);
+    archive::verify_lute_archives(&lcfg, is_prebuilt);
+    if cli_in_target_build {
+        cli::export_cli(&dst.join("build"));
+    }
+    if lcfg.link_mode == LinkMode::Shared {
+        shared::link_shared_runtime(lcfg);
+    }
+
+    report::record_lconfig(&lcfg);
+    report::record_source("cmake");
+    report::record_crt(static_crt);
+    report::record_cmake_build(&dst.join("build"));
+    for (phase, elapsed) in cmake.timings() {
+        report::record_phase(phase, *elapsed);
+    }
+    report::write();
+
+    dst
+}
+
+/// Sets the Luau/Lute build options and VM flags for `lcfg` on the lute
+/// project, shared by the target build and the host CLI build
+pub(crate) fn define_lute_options(cmake: &mut Config, lcfg: &LConfig) {
+    cmake
+        .define("LUAU_EXTERN_C", "ON") // Provides DLUA_USE_LONGJMP, DLUA_API, LUACODE_API, LUACODEGEN_API
         .define("LUAU_BUILD_STATIC", "ON")
         .define("LUTE_DISABLE_NET", if lcfg.disable_net { "ON" } else { "OFF" } )
         .define("LUTE_DISABLE_CRYPTO", if lcfg.disable_crypto { "ON" } else { "OFF" }  )
@@ -38,71 +158,132 @@ pub fn setup_lute_cmake(lcfg: LConfig, is_prebuilt: bool) -> std::path::PathBuf
         .cxxflag("-DLUAI_MAXCSTACK=1000000")
         .cxxflag("-DLUA_UTAG_LIMIT=128")
         .cxxflag("-DLUA_LUTAG_LIMIT=128") 
-        .cxxflag("-DLUA_USE_LONGJMP=1") // Use longjmp for error handling
-        .cxxflag(
-            "-fexceptions" // Enable C++ exceptions on non-Windows
-        )
-        .init_cxx_cfg(config)
-        .no_build_target(true)
-        .static_crt(static_crt)
-        .build()
+        .cxxflag("-DLUA_USE_LONGJMP=1"); // Use longjmp for error handling
 }
 
-pub fn build_cc_lute_lib(lcfg: LConfig, lib_name: &str, files: Vec<String>, is_prebuilt: bool) {
-    let static_crt = if is_prebuilt {
-        // Prebuilt lute runtime uses dynamic CRT
-        false
-    } else {
-        // Build lute runtime from source, use static CRT
-        true
-    };
+/// Include directories (relative to the crate root) Lute code compiled through
+/// `cc` gets
+pub const LUTE_CC_INCLUDES: &[&str] = &[
+    "lute/lute/cli/include",
+    "lute/lute/crypto/include",
+    "lute/lute/fs/include",
+    "lute/lute/luau/include",
+    "lute/lute/net/include",
+    "lute/lute/process/include",
+    "lute/lute/system/include",
+    "lute/lute/vm/include",
+    "lute/lute/task/include",
+    "lute/lute/time/include",
+    "lute/lute/runtime/include",
+    "lute/extern/luau/VM/include",
+    "lute/extern/luau/VM/src",
+    "lute/extern/luau/Common/include",
+    "lute/extern/luau/Compiler/include",
+    "lute/extern/libuv/include",
+];
+
+/// Returns a C++ `cc::Build` with the defines and include directories Lute and
+/// Luau sources must be compiled with (the `cc` counterpart of the CMake
+/// build's flags)
+pub fn lute_cc_build(lcfg: LConfig, static_crt: bool) -> cc::Build {
+    for include in LUTE_CC_INCLUDES {
+        rerun_if_changed(include);
+    }
 
+    let target = std::env::var("TARGET").unwrap();
     let mut build = cc::Build::new();
 
     build
         .cpp(true)
 	    .std("c++20")
-        .files(files)
-        .flag("-DLUA_USE_LONGJMP=1")
-        .flag("-DLUA_API=extern \"C\"")
-        .flag("-DLUACODE_API=extern \"C\"")
-        .flag("-DLUACODEGEN_API=extern \"C\"")
-        .flag("-DLUAI_MAXCSTACK=1000000")
-        .flag("-DLUA_UTAG_LIMIT=128") 
-        .flag("-DLUA_LUTAG_LIMIT=128") 
         .flag_if_supported(
-            "-fexceptions" // Enable C++ exceptions on non-Windows
+            wasm::exceptions_flag(&lcfg, &target) // Enable C++ exceptions on non-Windows
         )
-        .include("lute/lute/cli/include")
-        .include("lute/lute/crypto/include")
-        .include("lute/lute/fs/include")
-        .include("lute/lute/luau/include")
-        .include("lute/lute/net/include")
-        .include("lute/lute/process/include")
-        .include("lute/lute/system/include")
-        .include("lute/lute/vm/include")
-        .include("lute/lute/task/include")
-        .include("lute/lute/time/include")
-        .include("lute/lute/runtime/include")
-        .include("lute/extern/luau/VM/include")
-        .include("lute/extern/luau/VM/src")
-        .include("lute/extern/luau/Common/include")
-        .include("lute/extern/luau/Compiler/include")
-        .include("lute/extern/libuv/include")
         .static_crt(static_crt);
 
+    build.includes(lute_cc_include_paths(&lcfg));
+
+    // finalize_build links the C++ standard library (see LConfig::cpp_stdlib)
+    build.cpp_link_stdlib(None);
+    if lcfg.cpp_stdlib == CppStdlib::LibCxx {
+        build.flag_if_supported("-stdlib=libc++");
+    }
+
+    toolchain::configure_cc(&lcfg, &mut build, true);
+    if wasm::is_wasi(&target) {
+        wasi::configure_build(&mut build);
+    }
+
+    for (name, value) in lute_cc_defines(&lcfg) {
+        build.flag(format!("-D{name}={value}"));
+    }
+
+    build
+}
+
+/// Returns the include directories of a `cc` build of Lute for `lcfg`: those
+/// of the system dependencies first, then [`LUTE_CC_INCLUDES`] (without the
+/// bundled libuv headers shadowing the system ones)
+pub fn lute_cc_include_paths(lcfg: &LConfig) -> Vec<std::path::PathBuf> {
+    let system_libuv = sysdeps::libuv_source(lcfg) != DepSource::Bundled;
+    let mut paths = sysdeps::include_paths(lcfg);
+    paths.extend(
+        LUTE_CC_INCLUDES
+            .iter()
+            .filter(|include| !(system_libuv && **include == "lute/extern/libuv/include"))
+            .map(std::path::PathBuf::from),
+    );
+    paths
+}
+
+/// Returns the preprocessor definitions Lute and Luau sources are compiled
+/// with through `cc` (also used for the generated bindings)
+pub fn lute_cc_defines(lcfg: &LConfig) -> Vec<(&'static str, &'static str)> {
+    let mut defines = vec![
+        ("LUA_USE_LONGJMP", "1"),
+        ("LUA_API", "extern \"C\""),
+        ("LUACODE_API", "extern \"C\""),
+        ("LUACODEGEN_API", "extern \"C\""),
+        ("LUAI_MAXCSTACK", "1000000"),
+        ("LUA_UTAG_LIMIT", "128"),
+        ("LUA_LUTAG_LIMIT", "128"),
+    ];
+
     if lcfg.disable_net {
-        build.flag("-DLUTE_DISABLE_NET=1");
+        defines.push(("LUTE_DISABLE_NET", "1"));
     }
 
     if lcfg.disable_crypto {
-        build.flag("-DLUTE_DISABLE_CRYPTO=1");
+        defines.push(("LUTE_DISABLE_CRYPTO", "1"));
     }
 
     if lcfg.disable_native_codegen {
-        build.flag("-DLUTE_DISABLE_NATIVE_CODEGEN=1");
+        defines.push(("LUTE_DISABLE_NATIVE_CODEGEN", "1"));
+    }
+
+    defines
+}
+
+pub fn build_cc_lute_lib(lcfg: LConfig, lib_name: &str, files: Vec<String>, is_prebuilt: bool) {
+    let lcfg = lcfg.for_target(&std::env::var("TARGET").unwrap());
+    let static_crt = finalize::static_crt(&lcfg, is_prebuilt);
+
+    for file in &files {
+        rerun_if_changed(file);
+    }
+
+    let mut build = lute_cc_build(lcfg, static_crt);
+    match lcfg.unity_build {
+        Some(unity) => build.files(unity::unity_sources(lib_name, &files, &unity)),
+        None => build.files(&files),
+    };
+
+    if lcfg.export_compile_commands {
+        // The database lists the original files so that IDEs can resolve them
+        compdb::add_cc_build(&build, &files);
+        compdb::write(&lcfg);
     }
 
     build
         .compile(lib_name);
-}
\ No newline at end of file
+}
@@ -0,0 +1,114 @@
+//! Merged compilation database (`compile_commands.json`) covering both the
+//! CMake-built Lute runtime and the files compiled through `build_cc_lute_lib`.
+//!
+//! CMake writes its own database when `CMAKE_EXPORT_COMPILE_COMMANDS` is set;
+//! the cc-built files get entries generated from the exact `cc::Build` used to
+//! compile them. Both are merged into `OUT_DIR/compile_commands.json`, whose
+//! path is exported as the `compile_commands`
+//! [cargo metadata](crate#metadata-for-dependents) key.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::LConfig;
+use crate::json::Json;
+
+/// Name of the merged database written into `OUT_DIR`
+pub const COMPILE_COMMANDS_FILE_NAME: &str = "compile_commands.json";
+
+struct CompDb {
+    cmake_dbs: Vec<PathBuf>,
+    entries: Vec<Json>,
+    metadata_emitted: bool,
+}
+
+static COMPDB: Mutex<CompDb> = Mutex::new(CompDb {
+    cmake_dbs: Vec::new(),
+    entries: Vec::new(),
+    metadata_emitted: false,
+});
+
+/// Registers the database CMake exported into `build_dir`
+pub(crate) fn add_cmake_build(build_dir: &Path) {
+    let db = build_dir.join(COMPILE_COMMANDS_FILE_NAME);
+    let mut compdb = COMPDB.lock().unwrap();
+    if !compdb.cmake_dbs.contains(&db) {
+        compdb.cmake_dbs.push(db);
+    }
+}
+
+/// Adds an entry for each of `files`, compiled with `build`
+pub(crate) fn add_cc_build(build: &cc::Build, files: &[String]) {
+    let compiler = build.get_compiler();
+    let directory = std::env::current_dir().unwrap();
+
+    let mut compdb = COMPDB.lock().unwrap();
+    for file in files {
+        let file = directory.join(file);
+        let mut arguments = vec![Json::str(compiler.path().display().to_string())];
+        arguments.extend(
+            compiler
+                .args()
+                .iter()
+                .map(|arg| Json::str(arg.to_string_lossy())),
+        );
+        arguments.push(Json::str(if compiler.is_like_msvc() { "/c" } else { "-c" }));
+        arguments.push(Json::str(file.display().to_string()));
+
+        compdb.entries.push(Json::object([
+            ("directory", Json::str(directory.display().to_string())),
+            ("arguments", Json::Array(arguments)),
+            ("file", Json::str(file.display().to_string())),
+        ]));
+    }
+}
+
+/// Writes the merged database into `OUT_DIR` (and to
+/// [`LConfig::compile_commands_path`] if set), returning its path.
+pub(crate) fn write(lcfg: &LConfig) -> Option<PathBuf> {
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR")?);
+    let path = out_dir.join(COMPILE_COMMANDS_FILE_NAME);
+    let mut compdb = COMPDB.lock().unwrap();
+
+    // Entries are concatenated as text so that CMake's own entries are kept
+    // verbatim without having to parse them
+    let mut entries = Vec::new();
+    for db in &compdb.cmake_dbs {
+        let Ok(contents) = std::fs::read_to_string(db) else {
+            println!("cargo:warning=CMake did not export {}", db.display());
+            continue;
+        };
+        let inner = contents
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim();
+        if !inner.is_empty() {
+            entries.push(inner.to_string());
+        }
+    }
+    entries.extend(compdb.entries.iter().map(|e| e.pretty().trim().to_string()));
+
+    let merged = format!("[\n{}\n]\n", entries.join(",\n"));
+    if let Err(e) = std::fs::write(&path, merged) {
+        println!("cargo:warning=failed to write {}: {}", path.display(), e);
+        return None;
+    }
+
+    if let Some(copy_to) = lcfg.compile_commands_path {
+        if let Err(e) = std::fs::copy(&path, copy_to) {
+            println!(
+                "cargo:warning=failed to copy {} to {}: {}",
+                path.display(),
+                copy_to,
+                e
+            );
+        }
+    }
+
+    if !compdb.metadata_emitted {
+        println!("cargo:compile_commands={}", path.display());
+        compdb.metadata_emitted = true;
+    }
+    Some(path)
+}
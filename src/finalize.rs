@@ -1,97 +1,454 @@
-use crate::LConfig;
+use std::path::PathBuf;
 
-/// Finalizes the build process by linking all the necessary libraries
-/// in the right order (GNU ld needs the libraries to be linked in exact
-/// dependency order).
+use crate::sysdeps;
+use crate::{CppStdlib, CrtMode, LConfig, LinkMode, LinkModifier, Profile, musl, prefix, report, rerun::{rerun_if_env_changed, warn_once}, search::static_lib_file_name, wasm};
+
+/// The kind of a `cargo:rustc-link-lib` entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// `static=`
+    Static,
+    /// `dylib=`
+    Dylib,
+    /// No explicit kind (lets rustc pick, used for system libraries)
+    Default,
+}
+
+/// A single library to link, in the form of a `cargo:rustc-link-lib` entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkLib {
+    pub kind: LinkKind,
+    /// Library name, without `lib` prefix or extension (even with `+verbatim`)
+    pub name: String,
+    pub modifiers: Vec<LinkModifier>,
+}
+
+impl LinkLib {
+    fn new(kind: LinkKind, name: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            modifiers: Vec::new(),
+        }
+    }
+
+    /// Returns true if the `+verbatim` modifier is set
+    pub fn is_verbatim(&self) -> bool {
+        self.modifiers.contains(&LinkModifier::Verbatim(true))
+    }
+
+    /// Returns the file name of the library for `target` (static libraries only)
+    pub fn file_name(&self, target: &str) -> String {
+        static_lib_file_name(&self.name, target)
+    }
+
+    // Returns the name passed to rustc, which is the file name with `+verbatim`
+    fn link_name(&self) -> String {
+        if self.is_verbatim() {
+            self.file_name(&std::env::var("TARGET").unwrap_or_default())
+        } else {
+            self.name.clone()
+        }
+    }
+
+    fn static_lib(name: &str) -> Self {
+        Self::new(LinkKind::Static, name)
+    }
+
+    fn system(name: &str) -> Self {
+        Self::new(LinkKind::Default, name)
+    }
+}
+
+impl std::fmt::Display for LinkLib {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            LinkKind::Static => "static",
+            LinkKind::Dylib => "dylib",
+            LinkKind::Default => return write!(f, "{}", self.name),
+        };
+        write!(f, "{kind}")?;
+        for (i, modifier) in self.modifiers.iter().enumerate() {
+            write!(f, "{}{modifier}", if i == 0 { ':' } else { ',' })?;
+        }
+        write!(f, "={}", self.link_name())
+    }
+}
+
+/// Name of the shared runtime library built in [`LinkMode::Shared`] mode
+pub const SHARED_LIB_NAME: &str = "lute";
+
+/// Returns the libraries to link for `lcfg`, in the right order (GNU ld needs
+/// the libraries to be linked in exact dependency order).
 ///
-/// Prior to calling this, it is the job of the caller to set the linker 
-/// search path.
-pub fn finalize_build(lcfg: LConfig, prebuilt: bool) {
-    println!("cargo:rustc-env=LUAU_VERSION=0.678"); // TODO: Update when needed
+/// In [`LinkMode::Shared`] mode this is just the shared runtime library, see
+/// [`static_link_libraries`] for what it is made of.
+pub fn link_libraries(lcfg: LConfig, prebuilt: bool) -> Vec<LinkLib> {
+    match lcfg.link_mode {
+        LinkMode::Static => {
+            let mut libs = static_link_libraries(lcfg, prebuilt);
+            libs.extend(cpp_stdlib_libs(&lcfg));
+            apply_link_modifiers(&mut libs, lcfg);
+            libs
+        }
+        LinkMode::Shared => {
+            if prebuilt {
+                panic!("LinkMode::Shared requires building the Lute runtime from source");
+            }
+            if !lcfg.link_modifiers.is_empty() {
+                println!("cargo:warning=link_modifiers are ignored with LinkMode::Shared");
+            }
+            vec![LinkLib::new(LinkKind::Dylib, SHARED_LIB_NAME)]
+        }
+    }
+}
+
+/// Applies [`LConfig::link_modifiers`] to `libs`, validating them the way
+/// rustc would (to fail early with a clear message)
+fn apply_link_modifiers(libs: &mut [LinkLib], lcfg: LConfig) {
+    for (name, modifiers) in lcfg.link_modifiers {
+        let lib = libs
+            .iter_mut()
+            .find(|lib| lib.name == *name)
+            .unwrap_or_else(|| panic!("link_modifiers: {name} is not part of the link list"));
+        if lib.kind != LinkKind::Static {
+            panic!("link_modifiers: {name} is not a static library");
+        }
+
+        for modifier in *modifiers {
+            if lib
+                .modifiers
+                .iter()
+                .any(|m| std::mem::discriminant(m) == std::mem::discriminant(modifier))
+            {
+                panic!("link_modifiers: multiple {modifier:?} modifiers given for {name}");
+            }
+            lib.modifiers.push(*modifier);
+        }
+    }
+}
+
+/// The third party components each optional Lute module needs
+pub const MODULE_COMPONENTS: &[(&str, &[&str])] = &[
+    ("Lute.Crypto", &["decrepit", "crypto", "sodium"]),
+    ("Lute.Net", &["uSockets", "curl", "ssl", "pki", "decrepit", "crypto", "z"]),
+];
+
+/// Components in link order (dependents before their dependencies). libuv
+/// and zlib are linked after these, see [`static_link_libraries`].
+pub const COMPONENT_LINK_ORDER: &[&str] = &["uSockets", "curl", "ssl", "pki", "decrepit", "crypto", "sodium"];
+
+/// Returns the Lute modules with third party dependencies enabled in `lcfg`
+fn enabled_component_modules(lcfg: &LConfig) -> Vec<&'static str> {
+    let mut modules = Vec::new();
+    if lcfg.profile == Profile::VmOnly {
+        return modules;
+    }
+    if !lcfg.disable_crypto {
+        modules.push("Lute.Crypto");
+    }
+    if !lcfg.disable_net {
+        modules.push("Lute.Net");
+    }
+    modules
+}
+
+/// Returns the third party components (see [`MODULE_COMPONENTS`]) needed by
+/// the modules enabled in `lcfg`
+pub fn required_components(lcfg: &LConfig) -> Vec<&'static str> {
+    let modules = enabled_component_modules(lcfg);
+    let mut components = Vec::new();
+    for (module, needs) in MODULE_COMPONENTS {
+        if modules.contains(module) {
+            for component in *needs {
+                if !components.contains(component) {
+                    components.push(*component);
+                }
+            }
+        }
+    }
+    components
+}
+
+/// Returns the CMake targets to build for `lcfg`: the Luau/Lute libraries of
+/// the link list, plus the bundled BoringSSL components which are not always
+/// dependencies of them. Other third party libraries (libuv, curl, ...) are
+/// built as dependencies of the Lute modules.
+pub fn cmake_build_targets(lcfg: LConfig) -> Vec<String> {
+    const BORINGSSL_COMPONENTS: &[&str] = &["ssl", "pki", "decrepit", "crypto"];
+    static_link_libraries(lcfg, false)
+        .into_iter()
+        .filter(|lib| {
+            lib.kind == LinkKind::Static
+                && (lib.name.starts_with("Luau.")
+                    || lib.name.starts_with("Lute.")
+                    || BORINGSSL_COMPONENTS.contains(&lib.name.as_str()))
+        })
+        .map(|lib| lib.name)
+        .collect()
+}
+
+/// Returns the static libraries (and system libraries they need) making up
+/// the Lute runtime for `lcfg`, in link order
+pub fn static_link_libraries(lcfg: LConfig, prebuilt: bool) -> Vec<LinkLib> {
+    let mut libs = Vec::new();
+    sysdeps::check_sources(&lcfg, prebuilt);
+    if lcfg.profile == Profile::VmOnly {
+        libs.push(LinkLib::static_lib("Luau.Compiler"));
+        libs.push(LinkLib::static_lib("Luau.Ast"));
+        if !lcfg.disable_native_codegen {
+            libs.push(LinkLib::static_lib("Luau.CodeGen"));
+        }
+        libs.push(LinkLib::static_lib("Luau.VM"));
+        push_wasi_runtime(&mut libs);
+        return libs;
+    }
     if prebuilt {
         // Link in Luau.LuteExt and Luau.Custom
-        println!("cargo:rustc-link-lib=static=Luau.Custom");
-        println!("cargo:rustc-link-lib=static=Luau.LuteExt");
+        libs.push(LinkLib::static_lib("Luau.Custom"));
+        libs.push(LinkLib::static_lib("Luau.LuteExt"));
     }
 
-    println!("cargo:rustc-link-lib=static=Lute.Luau");
-    println!("cargo:rustc-link-lib=static=Luau.Compiler");
-    println!("cargo:rustc-link-lib=static=Luau.Analysis");
-    println!("cargo:rustc-link-lib=static=Luau.Ast");
+    libs.push(LinkLib::static_lib("Lute.Luau"));
+    libs.push(LinkLib::static_lib("Luau.Compiler"));
+    libs.push(LinkLib::static_lib("Luau.Analysis"));
+    libs.push(LinkLib::static_lib("Luau.Ast"));
     if !lcfg.disable_native_codegen {
-        println!("cargo:rustc-link-lib=static=Luau.CodeGen");
+        libs.push(LinkLib::static_lib("Luau.CodeGen"));
     }
-    println!("cargo:rustc-link-lib=static=Luau.Config");
-    println!("cargo:rustc-link-lib=static=Luau.EqSat");
-    println!("cargo:rustc-link-lib=static=Luau.VM");
+    libs.push(LinkLib::static_lib("Luau.Config"));
+    libs.push(LinkLib::static_lib("Luau.EqSat"));
+    libs.push(LinkLib::static_lib("Luau.VM"));
     if !lcfg.disable_crypto {
-        println!("cargo:rustc-link-lib=static=Lute.Crypto");
+        libs.push(LinkLib::static_lib("Lute.Crypto"));
     }
-    println!("cargo:rustc-link-lib=static=Lute.Fs");
+    libs.push(LinkLib::static_lib("Lute.Fs"));
     if !lcfg.disable_net {
-        println!("cargo:rustc-link-lib=static=Lute.Net");
-    }
-    println!("cargo:rustc-link-lib=static=Lute.Process");
-    println!("cargo:rustc-link-lib=static=Lute.System");
-    println!("cargo:rustc-link-lib=static=Lute.Task");
-    println!("cargo:rustc-link-lib=static=Lute.Time");
-    println!("cargo:rustc-link-lib=static=Lute.VM");
-    println!("cargo:rustc-link-lib=static=Lute.Require");
-    println!("cargo:rustc-link-lib=static=Lute.Std");
-    println!("cargo:rustc-link-lib=static=Lute.Runtime");
-    println!("cargo:rustc-link-lib=static=Luau.Require");
-    println!("cargo:rustc-link-lib=static=Luau.RequireNavigator"); 
-    println!("cargo:rustc-link-lib=static=Luau.CLI.lib");
-    if !lcfg.disable_net {
-        println!("cargo:rustc-link-lib=static=uSockets");
+        libs.push(LinkLib::static_lib("Lute.Net"));
+    }
+    libs.push(LinkLib::static_lib("Lute.Process"));
+    libs.push(LinkLib::static_lib("Lute.System"));
+    libs.push(LinkLib::static_lib("Lute.Task"));
+    libs.push(LinkLib::static_lib("Lute.Time"));
+    libs.push(LinkLib::static_lib("Lute.VM"));
+    libs.push(LinkLib::static_lib("Lute.Require"));
+    libs.push(LinkLib::static_lib("Lute.Std"));
+    libs.push(LinkLib::static_lib("Lute.Runtime"));
+    libs.push(LinkLib::static_lib("Luau.Require"));
+    libs.push(LinkLib::static_lib("Luau.RequireNavigator"));
+    libs.push(LinkLib::static_lib("Luau.CLI.lib"));
+    // uSockets, curl, BoringSSL and libsodium, as needed by the enabled modules
+    let components = required_components(&lcfg);
+    for component in COMPONENT_LINK_ORDER {
+        if components.contains(component) {
+            libs.push(LinkLib::static_lib(component));
+        }
     }
 
-    if !lcfg.disable_net || !lcfg.disable_crypto {
-        println!("cargo:rustc-link-lib=static=crypto");
-        println!("cargo:rustc-link-lib=static=decrepit");
-        println!("cargo:rustc-link-lib=static=pki");
-        println!("cargo:rustc-link-lib=static=ssl");
+    // libuv (bundled) and zlib
+    let system_libuv = sysdeps::system_libuv(&lcfg);
+    if system_libuv.is_none() {
+        let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+        // Named libuv.lib for MSVC and libuv.a otherwise
+        libs.push(LinkLib::static_lib(if target_env == "msvc" { "libuv" } else { "uv" }));
+    }
+    if components.contains(&"z") {
+        libs.push(LinkLib::static_lib("z"));
     }
 
-    if !lcfg.disable_crypto {
-        // libsodium
-        println!("cargo:rustc-link-lib=static=sodium");
+    // The libraries `pkg-config` reports for the system libuv, or those the
+    // CMake project links the bundled one with (the static library doesn't
+    // carry them)
+    match system_libuv {
+        Some(system) => libs.extend(system.libs.iter().map(|lib| LinkLib::system(lib))),
+        None => {
+            let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+            libs.extend(uv_system_libs(&target_os).iter().map(|lib| LinkLib::system(lib)));
+        }
     }
-    
-    if !lcfg.disable_net {
-        println!("cargo:rustc-link-lib=static=curl");
+
+    push_wasi_runtime(&mut libs);
+    libs
+}
+
+/// Returns the system libraries the bundled libuv needs on `target_os`
+/// (`CARGO_CFG_TARGET_OS`)
+pub fn uv_system_libs(target_os: &str) -> &'static [&'static str] {
+    match target_os {
+        // Lowercase, as MinGW ships them (and MSVC doesn't care)
+        "windows" => &[
+            "user32", "ws2_32", "iphlpapi", "psapi", "userenv", "advapi32", "ole32", "shell32", "dbghelp",
+        ],
+        "linux" => &["pthread", "dl", "rt"],
+        "android" => &["dl"],
+        "freebsd" | "dragonfly" | "openbsd" | "netbsd" => &["kvm"],
+        _ => &[],
     }
+}
 
-    // libuv
-    #[cfg(not(target_os = "windows"))]
-    {
-        println!("cargo:rustc-link-lib=static=uv");
+// Adds the C++ runtime with wasm exceptions, setjmp/longjmp and the POSIX
+// emulation libraries the stub libuv and Lute are compiled against on WASI
+fn push_wasi_runtime(libs: &mut Vec<LinkLib>) {
+    if wasm::is_wasi(&std::env::var("TARGET").unwrap_or_default()) {
+        for lib in ["c++", "c++abi", "unwind", "setjmp"].iter().chain(wasm::WASI_EMULATION_LIBS) {
+            libs.push(LinkLib::system(lib));
+        }
     }
-    #[cfg(target_os = "windows")]
-    {
-        println!("cargo:rustc-link-lib=User32"); // Solves the __imp_TranslateMessage error
-        println!("cargo:rustc-link-lib=Ws2_32"); // For sockets
-        println!("cargo:rustc-link-lib=Iphlpapi");
-        println!("cargo:rustc-link-lib=Psapi");
-        println!("cargo:rustc-link-lib=Userenv");
-        println!("cargo:rustc-link-lib=Advapi32");
-        println!("cargo:rustc-link-lib=Ole32");
-        println!("cargo:rustc-link-lib=Shell32");
+}
+
+/// Returns whether the Lute libraries use the static CRT for `lcfg` (see
+/// [`CrtMode`]), checking that this agrees with the `crt-static` target
+/// feature of the Rust build on MSVC targets
+pub fn static_crt(lcfg: &LConfig, prebuilt: bool) -> bool {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let rust_crt_static = std::env::var("CARGO_CFG_TARGET_FEATURE")
+        .unwrap_or_default()
+        .split(',')
+        .any(|feature| feature == "crt-static");
 
-        println!("cargo:rustc-link-lib=static=libuv");
+    let static_crt = match lcfg.crt {
+        // From source builds have always used the static CRT
+        CrtMode::Auto => !prebuilt,
+        CrtMode::Static => {
+            if prebuilt {
+                panic!("CrtMode::Static is not available with prebuilts, which use the dynamic CRT");
+            }
+            true
+        }
+        CrtMode::Dynamic => false,
+    };
+
+    if target.contains("msvc") && static_crt != rust_crt_static {
+        let message = format!(
+            "The Lute runtime uses the {} CRT but the Rust build {} the crt-static target feature, \
+             which fails to link (LNK2038); change LConfig::crt or RUSTFLAGS",
+            if static_crt { "static" } else { "dynamic" },
+            if rust_crt_static { "enables" } else { "does not enable" },
+        );
+        // Only an explicit choice is known to be wrong, the default is kept
+        // as it always was
+        if lcfg.crt == CrtMode::Auto {
+            warn_once(&message);
+        } else {
+            panic!("{message}");
+        }
     }
+    static_crt
+}
 
-    // zlib (system)
-    if !lcfg.disable_net {
-        println!("cargo:rustc-link-lib=static=z"); 
+/// Returns the C++ standard library entries of the link list for `lcfg` (see
+/// [`CppStdlib`])
+pub fn cpp_stdlib_libs(lcfg: &LConfig) -> Vec<LinkLib> {
+    let target = std::env::var("TARGET").unwrap();
+    if target.contains("msvc") && !matches!(lcfg.cpp_stdlib, CppStdlib::Auto | CppStdlib::None) {
+        panic!("MSVC targets always use the MSVC STL, use CppStdlib::Auto");
     }
 
-    if prebuilt {
-        // Configure C++ here (todo: determine if its useful for non-prebuilt as well) 
-        if let Some(ref cpp_stdlib) = get_cpp_link_stdlib(&std::env::var("TARGET").unwrap(), &std::env::var("HOST").unwrap()) {
-            println!("cargo:rustc-link-lib={cpp_stdlib}");
+    match lcfg.cpp_stdlib {
+        CppStdlib::Auto => get_cpp_link_stdlib(&target, &std::env::var("HOST").unwrap())
+            .map(|stdlib| LinkLib::system(&stdlib))
+            .into_iter()
+            .collect(),
+        CppStdlib::LibStdCxx => vec![LinkLib::system("stdc++")],
+        // Fully static musl binaries can't link a shared libc++
+        CppStdlib::LibCxx if musl::is_musl(&target) => vec![unbundled_static_lib("c++"), unbundled_static_lib("c++abi")],
+        CppStdlib::LibCxx => vec![LinkLib::system("c++")],
+        CppStdlib::StaticLibStdCxx => vec![unbundled_static_lib("stdc++")],
+        CppStdlib::None => Vec::new(),
+    }
+}
+
+// Not bundled into the rlib: the final link gets the C++ standard library
+// archive once
+fn unbundled_static_lib(name: &str) -> LinkLib {
+    let mut lib = LinkLib::static_lib(name);
+    lib.modifiers.push(LinkModifier::Bundle(false));
+    lib
+}
+
+/// Returns the directory containing `file_name` (e.g. `libstdc++.a`) in the
+/// C++ compiler's installation, as reported by `-print-file-name`
+pub fn compiler_file_dir(lcfg: &LConfig, file_name: &str) -> Option<PathBuf> {
+    #[cfg(feature = "full")]
+    let mut cmd = {
+        let mut build = cc::Build::new();
+        build.cpp(true).cargo_metadata(false).warnings(false);
+        crate::toolchain::configure_cc(lcfg, &mut build, true);
+        build.get_compiler().to_command()
+    };
+    #[cfg(not(feature = "full"))]
+    let mut cmd = {
+        let _ = lcfg;
+        let target = std::env::var("TARGET").unwrap_or_default();
+        std::process::Command::new(
+            musl::compiler(&target, true)
+                .or_else(|| std::env::var_os("CXX").map(PathBuf::from))
+                .unwrap_or_else(|| PathBuf::from("c++")),
+        )
+    };
+
+    let output = cmd.arg(format!("-print-file-name={file_name}")).output().ok()?;
+    let path = PathBuf::from(String::from_utf8(output.stdout).ok()?.trim());
+    // The bare file name is printed back when the file isn't found
+    if output.status.success() && path.is_absolute() && path.is_file() {
+        path.parent().map(|dir| dir.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Finalizes the build process by linking all the necessary libraries
+/// in the right order (see [`link_libraries`]).
+///
+/// Prior to calling this, it is the job of the caller to set the linker 
+/// search path (except for system dependencies found through `pkg-config`).
+pub fn finalize_build(lcfg: LConfig, prebuilt: bool) {
+    println!("cargo:rustc-env=LUAU_VERSION=0.678"); // TODO: Update when needed
+
+    let target = std::env::var("TARGET").unwrap();
+    let lcfg = lcfg.for_target(&target);
+
+    for path in sysdeps::link_search_paths(&lcfg) {
+        println!("cargo:rustc-link-search=native={}", path.display());
+    }
+    if wasm::is_wasi(&target) {
+        match wasm::wasi_sysroot_lib_dir(&target) {
+            Some(dir) => println!("cargo:rustc-link-search=native={}", dir.display()),
+            None => println!("cargo:warning=WASI_SDK_PATH is not set, libc++ may not be found"),
+        }
+    }
+
+    musl::check_crt_static(&target);
+    if lcfg.link_mode == LinkMode::Static {
+        // The shared runtime library was linked from the prefixed archives
+        prefix::prefix_lute_archives(&lcfg, prebuilt);
+    }
+
+    let libs = link_libraries(lcfg, prebuilt);
+    // Static C++ standard library archives live in the compiler's installation
+    for lib in libs.iter().filter(|lib| lib.kind == LinkKind::Static && matches!(lib.name.as_str(), "stdc++" | "c++" | "c++abi")) {
+        let file_name = lib.file_name(&target);
+        match compiler_file_dir(&lcfg, &file_name) {
+            Some(dir) => println!("cargo:rustc-link-search=native={}", dir.display()),
+            None => println!("cargo:warning=Could not locate {file_name} through the C++ compiler"),
         }
     }
+    for lib in &libs {
+        println!("cargo:rustc-link-lib={lib}");
+    }
+    // The link arguments only reach this package's own binaries, the final
+    // binary's build script prints them from the metadata (see `wasm`)
+    let link_args = wasm::link_args(&lcfg, &target);
+    for arg in &link_args {
+        println!("cargo:rustc-link-arg={arg}");
+    }
+    if !link_args.is_empty() {
+        println!("cargo:link_args={}", link_args.join(" "));
+    }
+
+    report::record_lconfig(&lcfg);
+    report::record_link_libraries(&libs);
+    report::write();
 }
 
 /// From mlua (https://github.com/mlua-rs/luau-src-rs/blob/7c89c42b25ce45dec72a15c4f430a0aa1a999897/src/lib.rs#L238C1-L269C1)
@@ -106,16 +463,22 @@ pub fn finalize_build(lcfg: LConfig, prebuilt: bool) {
 fn get_cpp_link_stdlib(target: &str, host: &str) -> Option<String> {
     // Try to get value from the `CXXSTDLIB` env variable
     let kind = if host == target { "HOST" } else { "TARGET" };
-    let res = std::env::var(format!("CXXSTDLIB_{target}"))
-        .or_else(|_| std::env::var(format!("CXXSTDLIB_{}", target.replace('-', "_"))))
-        .or_else(|_| std::env::var(format!("{kind}_CXXSTDLIB")))
-        .or_else(|_| std::env::var("CXXSTDLIB"))
-        .ok();
+    let vars = [
+        format!("CXXSTDLIB_{target}"),
+        format!("CXXSTDLIB_{}", target.replace('-', "_")),
+        format!("{kind}_CXXSTDLIB"),
+        "CXXSTDLIB".to_string(),
+    ];
+    for var in &vars {
+        rerun_if_env_changed(var);
+    }
+    let res = vars.iter().find_map(|var| std::env::var(var).ok());
     if res.is_some() {
         return res;
     }
 
-    if target.contains("msvc") {
+    if target.contains("msvc") || target.starts_with("wasm") {
+        // Emscripten links libc++ by itself, WASI gets it from the link list
         None
     } else if target.contains("apple") | target.contains("freebsd") | target.contains("openbsd")
     {
@@ -126,3 +489,77 @@ fn get_cpp_link_stdlib(target: &str, host: &str) -> Option<String> {
         Some("stdc++".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_gnu_link_list() {
+        // The only test describing a target through the environment, the way
+        // cargo does for build scripts
+        unsafe {
+            std::env::set_var("TARGET", "x86_64-pc-windows-gnu");
+            std::env::set_var("CARGO_CFG_TARGET_OS", "windows");
+            std::env::set_var("CARGO_CFG_TARGET_ENV", "gnu");
+        }
+        let libs = static_link_libraries(LConfig::default(), false);
+        let position = |name: &str, kind: LinkKind| {
+            libs.iter()
+                .position(|lib| lib.name == name && lib.kind == kind)
+                .unwrap_or_else(|| panic!("{name} is not linked: {libs:?}"))
+        };
+
+        let uv = position("uv", LinkKind::Static);
+        assert!(position("Lute.Runtime", LinkKind::Static) < uv);
+        // GNU ld needs the system libraries after the archives using them
+        for lib in ["ws2_32", "iphlpapi", "userenv", "psapi", "user32", "dbghelp"] {
+            assert!(position(lib, LinkKind::Default) > uv, "{lib} is linked before uv");
+        }
+        assert!(!libs.iter().any(|lib| lib.name == "libuv" || lib.name == "pthread"));
+    }
+
+    fn with_modifiers(modifiers: &'static [(&'static str, &'static [LinkModifier])]) -> Vec<LinkLib> {
+        let mut libs = vec![LinkLib::static_lib("Lute.Fs"), LinkLib::system("ws2_32")];
+        apply_link_modifiers(&mut libs, LConfig { link_modifiers: modifiers, ..Default::default() });
+        libs
+    }
+
+    #[test]
+    fn link_modifiers_are_passed_as_given() {
+        let libs = with_modifiers(&[("Lute.Fs", &[LinkModifier::WholeArchive(true), LinkModifier::Bundle(true)])]);
+        assert_eq!(libs[0].to_string(), "static:+whole-archive,+bundle=Lute.Fs");
+
+        let libs = with_modifiers(&[("Lute.Fs", &[LinkModifier::WholeArchive(true)])]);
+        assert_eq!(libs[0].to_string(), "static:+whole-archive=Lute.Fs");
+
+        let libs = with_modifiers(&[("Lute.Fs", &[LinkModifier::Bundle(false), LinkModifier::Verbatim(true)])]);
+        assert_eq!(libs[0].to_string(), "static:-bundle,+verbatim=libLute.Fs.a");
+        assert!(libs[0].is_verbatim());
+    }
+
+    #[test]
+    fn link_lib_display() {
+        assert_eq!(LinkLib::static_lib("Luau.VM").to_string(), "static=Luau.VM");
+        assert_eq!(LinkLib::new(LinkKind::Dylib, SHARED_LIB_NAME).to_string(), "dylib=lute");
+        assert_eq!(LinkLib::system("ws2_32").to_string(), "ws2_32");
+    }
+
+    #[test]
+    #[should_panic(expected = "link_modifiers: Lute.Net is not part of the link list")]
+    fn link_modifiers_need_a_linked_library() {
+        with_modifiers(&[("Lute.Net", &[LinkModifier::WholeArchive(true)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "link_modifiers: ws2_32 is not a static library")]
+    fn link_modifiers_need_a_static_library() {
+        with_modifiers(&[("ws2_32", &[LinkModifier::WholeArchive(true)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "link_modifiers: multiple Bundle(false) modifiers given for Lute.Fs")]
+    fn link_modifiers_are_unique() {
+        with_modifiers(&[("Lute.Fs", &[LinkModifier::Bundle(true), LinkModifier::Bundle(false)])]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Minimal JSON writer used for the machine-readable files this crate emits
+//! (build report, compilation database). Build scripts pulling in this crate
+//! shouldn't have to compile serde for a handful of small documents.
+
+use std::fmt::Write;
+
+/// A JSON value
+#[derive(Clone, Debug)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Int(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from `(key, value)` pairs, preserving their order
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Builds a string value
+    pub fn str<S: AsRef<str>>(s: S) -> Json {
+        Json::String(s.as_ref().to_string())
+    }
+
+    /// Builds a string value, or `null` if `s` is `None`
+    pub fn opt_str<S: AsRef<str>>(s: Option<S>) -> Json {
+        s.map_or(Json::Null, Json::str)
+    }
+
+    /// Renders the value as indented JSON
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Int(i) => write!(out, "{i}").unwrap(),
+            Json::String(s) => write_escaped(out, s),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    item.write(out, indent + 1);
+                    if i + 1 != items.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Json::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Json::Object(fields) => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    push_indent(out, indent + 1);
+                    write_escaped(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                    if i + 1 != fields.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                push_indent(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_escaped(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let value = Json::str("say \"hi\"\\ C:\\lute\n\r\t\u{1}\u{1f} é 🦀");
+        assert_eq!(value.pretty(), "\"say \\\"hi\\\"\\\\ C:\\\\lute\\n\\r\\t\\u0001\\u001f é 🦀\"\n");
+    }
+
+    #[test]
+    fn escapes_keys() {
+        let value = Json::object([("a\"b\\c\n", Json::Null)]);
+        assert_eq!(value.pretty(), "{\n  \"a\\\"b\\\\c\\n\": null\n}\n");
+    }
+
+    #[test]
+    fn empty_containers() {
+        assert_eq!(Json::Array(Vec::new()).pretty(), "[]\n");
+        assert_eq!(Json::Object(Vec::new()).pretty(), "{}\n");
+        let value = Json::object([("array", Json::Array(Vec::new())), ("object", Json::Object(Vec::new()))]);
+        assert_eq!(value.pretty(), "{\n  \"array\": [],\n  \"object\": {}\n}\n");
+    }
+
+    #[test]
+    fn nested_containers() {
+        let value = Json::object([
+            ("name", Json::str("lute")),
+            ("version", Json::opt_str(None::<&str>)),
+            (
+                "libs",
+                Json::Array(vec![
+                    Json::object([("name", Json::str("Luau.VM")), ("static", Json::Bool(true))]),
+                    Json::Array(vec![Json::Int(1), Json::Int(u64::MAX)]),
+                ]),
+            ),
+        ]);
+        let expected = r#"{
+  "name": "lute",
+  "version": null,
+  "libs": [
+    {
+      "name": "Luau.VM",
+      "static": true
+    },
+    [
+      1,
+      18446744073709551615
+    ]
+  ]
+}
+"#;
+        assert_eq!(value.pretty(), expected);
+    }
+}
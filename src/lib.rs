@@ -1,11 +1,276 @@
+//! Build logic shared by the `lute-src-rs` crates: builds the Lute runtime
+//! (from source with CMake or `cc`, or from the checked-in prebuilts) from a
+//! build script and links it, as configured by [`LConfig`].
+//!
+//! # Metadata for dependents
+//!
+//! Link arguments printed by a build script only apply to its own package, so
+//! what other crates need (paths of generated files, link arguments for the
+//! final binary, ...) is exported as `cargo:<key>=<value>` metadata. Cargo
+//! only passes it on if the crate building Lute declares `links = "lute"` in
+//! its `Cargo.toml`; the build scripts of the crates depending on it directly
+//! then read it as `DEP_LUTE_<KEY>`:
+//!
+//! | Key | Value |
+//! |---|---|
+//! | `link_args` | Space separated link arguments for the final binary (Emscripten, see the `wasm` module) |
+//! | `rpath`, `shared_lib` | Directory and path of the shared runtime library ([`LinkMode::Shared`]) |
+//! | `cli` | Path of the host `lute` CLI ([`LConfig::build_cli`]) |
+//! | `bindings` | Path of the generated Rust bindings (`bindgen` feature) |
+//! | `symbol_prefix`, `symbol_prefix_header` | [`LConfig::symbol_prefix`] and the header renaming the C API |
+//! | `compile_commands` | Path of the merged `compile_commands.json` ([`LConfig::export_compile_commands`]) |
+//! | `build_report` | Path of the JSON build report |
+//! | `zig_cc` | Path of the `zig cc` wrapper ([`Toolchain::Zig`]) |
+//! | `root` | CMake build directory |
+
 // cc
+#[cfg(feature = "bindgen")]
+pub mod bindings;
+#[cfg(feature = "full")]
+pub mod buildlog;
+#[cfg(feature = "full")]
+pub mod ccbackend;
+#[cfg(feature = "full")]
+pub mod cli;
 #[cfg(feature = "full")]
 pub mod cmake;
 #[cfg(feature = "full")]
 pub mod commonflags;
+#[cfg(feature = "full")]
+pub mod compdb;
+#[cfg(feature = "full")]
+pub mod shared;
+#[cfg(feature = "full")]
+pub mod toolchain;
+#[cfg(feature = "full")]
+pub mod unity;
+#[cfg(feature = "full")]
+pub mod wasi;
 
+pub mod archive;
 pub mod finalize;
+mod json;
+pub mod musl;
+mod object;
 pub mod prebuilts;
+pub mod prefix;
+pub mod report;
+pub mod rerun;
+pub mod search;
+pub mod sysdeps;
+pub mod wasm;
+
+/// How Lute is built from source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Build the lute CMake project (requires a system `cmake`)
+    #[default]
+    CMake,
+    /// Compile Luau, the Lute modules and libuv directly through `cc`, for
+    /// environments without CMake.
+    ///
+    /// Limited to the runtime without net and crypto: their third party
+    /// dependencies (BoringSSL, curl, libsodium, ...) are only built by CMake,
+    /// so `disable_net` and `disable_crypto` must be set.
+    Cc,
+}
+
+/// Compilers used to build Lute from source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Toolchain {
+    /// The compilers `cc`/CMake pick for the target (`CC_<target>`, ...)
+    #[default]
+    Default,
+    /// `zig cc`/`zig c++ -target <triple>` for every target, to cross compile
+    /// without per-target toolchains (see the `toolchain` module)
+    Zig,
+}
+
+/// Which C runtime the Lute runtime is compiled against (only meaningful for
+/// MSVC targets, where mixing `/MT` and `/MD` objects fails to link)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CrtMode {
+    /// The static CRT when building from source and the dynamic one for
+    /// prebuilts, as before `CrtMode` existed. A mismatch with the
+    /// `crt-static` target feature of the Rust build is only warned about,
+    /// set `Static`/`Dynamic` to have it rejected.
+    #[default]
+    Auto,
+    /// Static CRT (`/MT`), requires `-C target-feature=+crt-static`
+    Static,
+    /// Dynamic CRT (`/MD`)
+    Dynamic,
+}
+
+/// The C++ standard library the Lute runtime is compiled and linked against
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CppStdlib {
+    /// `CXXSTDLIB` (like the `cc` crate) or the platform default: libc++ on
+    /// Apple and BSDs, libc++_shared on Android, libstdc++ elsewhere and
+    /// nothing for MSVC
+    #[default]
+    Auto,
+    /// libstdc++ (`-lstdc++`)
+    LibStdCxx,
+    /// libc++ (`-stdlib=libc++` where the compiler supports it, `-lc++`)
+    LibCxx,
+    /// libstdc++ linked statically (`libstdc++.a`, `-static-libstdc++` for the
+    /// shared runtime library). What `Auto` and `LibStdCxx` mean on musl
+    /// targets, where libc++ is linked statically as well.
+    StaticLibStdCxx,
+    /// Don't link a C++ standard library, the consumer provides one
+    None,
+}
+
+/// Which part of the Lute stack is built and linked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// The whole Lute runtime: Luau, the Lute modules and their third party
+    /// dependencies (libuv, ...)
+    #[default]
+    Full,
+    /// Only the Luau VM, compiler and AST (plus `Luau.CodeGen` unless
+    /// `disable_native_codegen` is set), with the same VM flags as the full
+    /// runtime, for bare Luau embeddings. No libuv and no Lute modules:
+    /// `disable_net` and `disable_crypto` are implied. This is also the only
+    /// way to leave out the Luau type checker (`Luau.Analysis` and
+    /// `Luau.EqSat`, the largest archives by far): `Lute.Runtime` registers
+    /// the `@lute/luau` module built on it, and lute has no build option to
+    /// leave that module out, so there is no `LConfig` switch for it.
+    VmOnly,
+}
+
+/// How the Lute runtime is linked into the consumer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Link every Lute/Luau library statically
+    #[default]
+    Static,
+    /// Combine the runtime into a single shared library (`liblute.so`,
+    /// `liblute.dylib` or `lute.dll`) so that several cdylibs can share one
+    /// runtime instance. Requires building from source. The final binaries
+    /// need its rpath from their own build script, see the `shared` module.
+    Shared,
+}
+
+/// A `rustc-link-lib` linking modifier
+/// (see <https://doc.rust-lang.org/rustc/command-line-arguments.html#linking-modifiers-whole-archive>)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkModifier {
+    /// `+whole-archive`/`-whole-archive`: link every object of the archive,
+    /// even if nothing references it (e.g. modules registering themselves
+    /// through static initializers).
+    WholeArchive(bool),
+    /// `+bundle`/`-bundle`: whether the archive is bundled into the rlib or
+    /// passed to the final link
+    Bundle(bool),
+    /// `+verbatim`/`-verbatim`: pass the file name (`libfoo.a`/`foo.lib`) to
+    /// the linker as is
+    Verbatim(bool),
+}
+
+impl std::fmt::Display for LinkModifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (enabled, name) = match *self {
+            LinkModifier::WholeArchive(enabled) => (enabled, "whole-archive"),
+            LinkModifier::Bundle(enabled) => (enabled, "bundle"),
+            LinkModifier::Verbatim(enabled) => (enabled, "verbatim"),
+        };
+        write!(f, "{}{}", if enabled { '+' } else { '-' }, name)
+    }
+}
+
+/// Where libuv comes from. Anything but `Bundled` requires [`Backend::Cc`],
+/// see the `sysdeps` module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepSource {
+    /// The copy vendored in the lute tree, linked statically
+    #[default]
+    Bundled,
+    /// The system installed library, located through `pkg-config`. Falls back
+    /// to the bundled copy (with a warning) if `pkg-config` can't find it.
+    System,
+    /// The library built by a libuv `-sys` crate of the dependency graph
+    /// (with `links = "uv"`), found through its `DEP_UV_ROOT`/`DEP_UV_INCLUDE`
+    /// metadata, so that a single copy gets linked. That crate links the
+    /// library: it is left out of the link list. Requires the crate calling
+    /// the build functions to depend on the `-sys` crate (cargo only passes
+    /// `DEP_*` to direct dependents). Falls back to the bundled copy (with a
+    /// warning) if the metadata is missing.
+    Crate,
+}
+
+/// Source of the third party dependencies of the runtime which can be
+/// replaced. Only libuv can: the other ones (zlib, curl, BoringSSL and
+/// libsodium) belong to the net and crypto modules, which only the CMake
+/// backend builds, and that always uses the bundled copies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemDeps {
+    /// Ignored with [`Profile::VmOnly`], which doesn't use libuv
+    pub libuv: DepSource,
+}
+
+/// How C++ exceptions (used by Lute for error handling) are implemented on
+/// Emscripten
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WasmExceptions {
+    /// Emscripten's JavaScript based exceptions (`-fexceptions`), supported
+    /// everywhere but slow
+    #[default]
+    Js,
+    /// Native WebAssembly exception handling (`-fwasm-exceptions`), requires a
+    /// runtime supporting the exception handling proposal
+    Native,
+}
+
+/// Settings for Emscripten (`wasm32-unknown-emscripten`) targets, ignored for
+/// other targets. The link settings only apply to the final binary if its
+/// build script prints `DEP_LUTE_LINK_ARGS` (see the
+/// [metadata for dependents](crate#metadata-for-dependents) and the `wasm`
+/// module).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmscriptenConfig {
+    pub exceptions: WasmExceptions,
+    /// Link with `-sALLOW_MEMORY_GROWTH=1`
+    pub allow_memory_growth: bool,
+    /// Stack size in bytes (`-sSTACK_SIZE`), if not Emscripten's default
+    pub stack_size: Option<u32>,
+    /// C functions exported to JavaScript (`-sEXPORTED_FUNCTIONS`), a leading
+    /// underscore is added where missing
+    pub exported_functions: &'static [&'static str],
+}
+
+impl Default for EmscriptenConfig {
+    fn default() -> Self {
+        Self {
+            exceptions: WasmExceptions::Js,
+            allow_memory_growth: true,
+            stack_size: Some(5 * 1024 * 1024), // Emscripten's default before 3.1.27 (now 64 KiB, too small for Luau)
+            exported_functions: &[],
+        }
+    }
+}
+
+/// Settings for unity (jumbo) builds, where several source files are compiled
+/// as a single translation unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnityBuild {
+    /// Maximum number of source files combined into one translation unit
+    pub batch_size: usize,
+    /// Files which don't combine safely and are always compiled on their own,
+    /// matched against the trailing components of the path (e.g. `lgc.cpp` or
+    /// `VM/src/lgc.cpp`). Extends `unity::DEFAULT_UNITY_EXCLUDE`.
+    pub exclude: &'static [&'static str],
+}
+
+impl Default for UnityBuild {
+    fn default() -> Self {
+        Self {
+            batch_size: 16,
+            exclude: &[],
+        }
+    }
+}
 
 /// The list of configurable flags
 #[derive(Clone, Copy)]
@@ -13,6 +278,46 @@ pub struct LConfig {
     pub disable_crypto: bool,
     pub disable_net: bool,
     pub disable_native_codegen: bool,
+    /// Full Lute runtime or only the Luau VM, see [`Profile`]
+    pub profile: Profile,
+    /// Emit a merged `compile_commands.json` into `OUT_DIR` covering the CMake
+    /// built runtime and the files passed to `build_cc_lute_lib`
+    pub export_compile_commands: bool,
+    /// Additionally copy the merged `compile_commands.json` to this path
+    pub compile_commands_path: Option<&'static str>,
+    /// Build system used when building from source
+    pub backend: Backend,
+    /// Compile in unity mode (`CMAKE_UNITY_BUILD` for CMake, batched
+    /// translation units for `cc` builds)
+    pub unity_build: Option<UnityBuild>,
+    /// Compilers used when building from source
+    pub toolchain: Toolchain,
+    /// C runtime used by the Lute libraries
+    pub crt: CrtMode,
+    /// C++ standard library used by the Lute libraries
+    pub cpp_stdlib: CppStdlib,
+    /// Static or shared linkage of the runtime
+    pub link_mode: LinkMode,
+    /// Linking modifiers for individual static libraries of the link list,
+    /// e.g. `&[("Lute.Fs", &[LinkModifier::WholeArchive(true)])]`
+    pub link_modifiers: &'static [(&'static str, &'static [LinkModifier])],
+    /// Bundled or system copy of libuv (system and `-sys` crate copies with
+    /// `Backend::Cc` only)
+    pub system_deps: SystemDeps,
+    /// Emscripten specific settings
+    pub emscripten: EmscriptenConfig,
+    /// Prefix added to every symbol defined by the Luau/Lute archives (e.g.
+    /// `lute_` turns `lua_newstate` into `lute_lua_newstate`), so that another
+    /// Luau (such as mlua's) can be linked into the same binary. Applied by
+    /// rewriting the archives after the build, see the `prefix` module;
+    /// changing or removing it afterwards requires a `cargo clean`.
+    pub symbol_prefix: Option<&'static str>,
+    /// Also build the `lute` CLI for the host (separately when cross
+    /// compiling) and export its path as `LUTE_CLI` (and as the `cli`
+    /// [metadata for dependents](crate#metadata-for-dependents)), see the
+    /// `cli` module.
+    /// Requires building from source.
+    pub build_cli: bool,
 }
 
 impl Default for LConfig {
@@ -21,6 +326,37 @@ impl Default for LConfig {
             disable_crypto: true, // Takes too long to build
             disable_net: true, // Takes too long to build
             disable_native_codegen: true, // Limits portability when enabled, takes a bit to build
+            profile: Profile::Full,
+            export_compile_commands: false,
+            compile_commands_path: None,
+            backend: Backend::CMake,
+            unity_build: None,
+            toolchain: Toolchain::Default,
+            crt: CrtMode::Auto,
+            cpp_stdlib: CppStdlib::Auto,
+            link_mode: LinkMode::Static,
+            link_modifiers: &[],
+            system_deps: SystemDeps::default(),
+            emscripten: EmscriptenConfig::default(),
+            symbol_prefix: None,
+            build_cli: false,
+        }
+    }
+}
+
+impl LConfig {
+    /// Returns the configuration actually used for `target`, with the modules
+    /// it can't support turned off (warning about those explicitly enabled),
+    /// those outside of the [`Profile`] turned off and the C++ standard
+    /// library linked statically on musl targets.
+    ///
+    /// The build entry points call this themselves.
+    pub fn for_target(mut self, target: &str) -> Self {
+        if self.profile == Profile::VmOnly {
+            // There are no Lute modules to enable
+            self.disable_net = true;
+            self.disable_crypto = true;
         }
+        musl::restrict_lconfig(wasm::restrict_lconfig(self, target), target)
     }
 }
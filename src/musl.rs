@@ -0,0 +1,249 @@
+//! Fully static musl builds (`*-linux-musl*` targets).
+//!
+//! On musl targets the Lute runtime is compiled with a musl targeting C/C++
+//! compiler (see `toolchain::configure_cc`) and the C++ standard library is
+//! always linked statically, so that with Rust's default `crt-static` the
+//! final binary has no dynamic dependencies at all and runs `FROM scratch`.
+//! rustc itself passes `-static`/`-static-pie` to the linker in that case, so
+//! no extra link arguments are emitted.
+//!
+//! The final link happens after the build script, so the result can't be
+//! checked from it: use [`verify_fully_static`] in a test or CI step.
+
+use std::path::{Path, PathBuf};
+
+use crate::object::{Elf, PT_DYNAMIC, PT_INTERP, PT_LOAD};
+use crate::rerun::{rerun_if_env_changed, warn_once};
+use crate::{CppStdlib, LConfig};
+
+/// Returns true if `target` is a musl target
+pub fn is_musl(target: &str) -> bool {
+    target.contains("-musl")
+}
+
+/// Returns the musl targeting C (`cpp == false`) or C++ compiler to build
+/// for `target` with, when cross compiling to musl and the compiler isn't
+/// set through the environment variables `cc` reads (`CXX_<target>`,
+/// `TARGET_CXX`, `CXX`, ...).
+///
+/// cc falls back to the host (glibc) compiler when `musl-g++` doesn't exist,
+/// so the musl-cross-make style `<arch>-linux-musl-g++` is looked up in
+/// `PATH` first, then the `musl-gcc`/`musl-g++` wrappers. A warning is
+/// emitted if none is found.
+pub fn compiler(target: &str, cpp: bool) -> Option<PathBuf> {
+    let host = std::env::var("HOST").unwrap_or_default();
+    // The native compiler of a musl host (e.g. Alpine) targets musl already
+    if !is_musl(target) || host == target {
+        return None;
+    }
+
+    let tool = if cpp { "CXX" } else { "CC" };
+    let vars = [
+        format!("{tool}_{target}"),
+        format!("{tool}_{}", target.replace('-', "_")),
+        format!("TARGET_{tool}"),
+        tool.to_string(),
+    ];
+    for var in &vars {
+        rerun_if_env_changed(var);
+    }
+    if vars.iter().any(|var| std::env::var_os(var).is_some()) {
+        return None;
+    }
+
+    let parts = target.split('-').collect::<Vec<_>>();
+    let (arch, env) = (parts[0], parts[parts.len() - 1]);
+    let names = if cpp {
+        [format!("{arch}-linux-{env}-g++"), format!("{arch}-linux-{env}-c++"), "musl-g++".to_string()]
+    } else {
+        [format!("{arch}-linux-{env}-gcc"), format!("{arch}-linux-{env}-cc"), "musl-gcc".to_string()]
+    };
+    let found = names.iter().find_map(|name| find_in_path(name));
+    if found.is_none() {
+        warn_once(&format!(
+            "No musl {} compiler found for {} (tried {}), set {}_{} or use Toolchain::Zig",
+            if cpp { "C++" } else { "C" },
+            target,
+            names.join(", "),
+            tool,
+            target
+        ));
+    }
+    found
+}
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Warns if the Rust build disables `crt-static` for a musl target, in which
+/// case the binary dynamically links the musl loader
+pub(crate) fn check_crt_static(target: &str) {
+    let crt_static = std::env::var("CARGO_CFG_TARGET_FEATURE")
+        .unwrap_or_default()
+        .split(',')
+        .any(|feature| feature == "crt-static");
+    if is_musl(target) && !crt_static {
+        warn_once(&format!(
+            "crt-static is disabled for {target}, binaries linking Lute will not be fully static"
+        ));
+    }
+}
+
+/// Switches `lcfg` to a statically linked C++ standard library on musl
+/// targets, see [`LConfig::for_target`]
+pub(crate) fn restrict_lconfig(mut lcfg: LConfig, target: &str) -> LConfig {
+    if !is_musl(target) {
+        return lcfg;
+    }
+
+    match lcfg.cpp_stdlib {
+        CppStdlib::Auto => lcfg.cpp_stdlib = CppStdlib::StaticLibStdCxx,
+        CppStdlib::LibStdCxx => {
+            warn_once(&format!("libstdc++ is always linked statically on {target}"));
+            lcfg.cpp_stdlib = CppStdlib::StaticLibStdCxx;
+        }
+        // libc++ is linked statically by `cpp_stdlib_libs`
+        CppStdlib::LibCxx | CppStdlib::StaticLibStdCxx | CppStdlib::None => {}
+    }
+    lcfg
+}
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+
+/// Checks that the ELF executable at `path` is fully static: no program
+/// interpreter and no shared library dependencies (static-pie executables
+/// are accepted). Returns a description of the problem otherwise.
+pub fn verify_fully_static(path: &Path) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let elf = Elf::parse(&data).ok_or_else(|| format!("{} is not a valid ELF file", path.display()))?;
+
+    let mut needed_offsets = Vec::new();
+    let mut strtab_addr = None;
+    for ph in elf.program_headers() {
+        match ph.kind {
+            PT_INTERP => {
+                let interp = elf.c_str(ph.offset).unwrap_or("?");
+                return Err(format!("{} uses the program interpreter {}", path.display(), interp));
+            }
+            PT_DYNAMIC => {
+                let entry_size = if elf.is_64 { 16 } else { 8 };
+                for i in 0..ph.file_size / entry_size {
+                    let entry = ph.offset + i * entry_size;
+                    let (Some(tag), Some(value)) = (elf.word(entry), elf.word(entry + entry_size / 2)) else {
+                        break;
+                    };
+                    match tag {
+                        DT_NULL => break,
+                        DT_NEEDED => needed_offsets.push(value),
+                        DT_STRTAB => strtab_addr = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if needed_offsets.is_empty() {
+        return Ok(());
+    }
+    // DT_STRTAB is an address, find its file offset through the load segments
+    let strtab = strtab_addr.and_then(|addr| {
+        elf.program_headers()
+            .into_iter()
+            .find(|ph| ph.kind == PT_LOAD && (ph.vaddr..ph.vaddr + ph.file_size).contains(&addr))
+            .map(|ph| ph.offset + (addr - ph.vaddr))
+    });
+    let needed = needed_offsets
+        .iter()
+        .map(|offset| strtab.and_then(|strtab| elf.c_str(strtab + offset)).unwrap_or("?"))
+        .collect::<Vec<_>>();
+    Err(format!("{} dynamically links {}", path.display(), needed.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 64-bit little endian ELF file made of a load segment mapping the whole
+    // file at address 0, then one segment of each kind with the given contents
+    fn elf(segments: &[(u32, &[u8])]) -> Vec<u8> {
+        let headers = 64 + 56 * (segments.len() + 1);
+        let total = headers + segments.iter().map(|(_, data)| data.len()).sum::<usize>();
+        let mut file = vec![0u8; 64];
+        file[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        file[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        file[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        file[0x38..0x3a].copy_from_slice(&(segments.len() as u16 + 1).to_le_bytes());
+
+        let mut program_header = |kind: u32, offset: usize, size: usize| {
+            let mut ph = [0u8; 56];
+            ph[..4].copy_from_slice(&kind.to_le_bytes());
+            ph[0x08..0x10].copy_from_slice(&(offset as u64).to_le_bytes());
+            ph[0x10..0x18].copy_from_slice(&(offset as u64).to_le_bytes());
+            ph[0x20..0x28].copy_from_slice(&(size as u64).to_le_bytes());
+            file.extend_from_slice(&ph);
+        };
+        program_header(PT_LOAD, 0, total);
+        let mut offset = headers;
+        for (kind, data) in segments {
+            program_header(*kind, offset, data.len());
+            offset += data.len();
+        }
+        for (_, data) in segments {
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn verify(name: &str, data: &[u8]) -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("lute-musl-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let result = verify_fully_static(&path);
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn static_executables_pass() {
+        assert_eq!(verify("static", &elf(&[])), Ok(()));
+        // static-pie: dynamic section for the self relocation, but no libraries
+        let dynamic = [DT_NULL.to_le_bytes(), 0u64.to_le_bytes()].concat();
+        assert_eq!(verify("static-pie", &elf(&[(PT_DYNAMIC, &dynamic)])), Ok(()));
+    }
+
+    #[test]
+    fn dynamic_executables_fail() {
+        let err = verify("interp", &elf(&[(PT_INTERP, b"/lib/ld-musl-x86_64.so.1\0")])).unwrap_err();
+        assert!(err.ends_with("uses the program interpreter /lib/ld-musl-x86_64.so.1"), "{err}");
+
+        // The string table is the first segment after the headers
+        let strtab = 64 + 56 * 3u64;
+        let dynamic = [DT_NEEDED, 1, DT_STRTAB, strtab, DT_NULL, 0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        let file = elf(&[(0, b"\0libstdc++.so.6\0"), (PT_DYNAMIC, &dynamic)]);
+        let err = verify("needed", &file).unwrap_err();
+        assert!(err.ends_with("dynamically links libstdc++.so.6"), "{err}");
+
+        assert!(verify("garbage", b"#!/bin/sh\n").unwrap_err().ends_with("is not a valid ELF file"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn current_executable() {
+        let result = verify_fully_static(&std::env::current_exe().unwrap());
+        if cfg!(all(target_env = "musl", target_feature = "crt-static")) {
+            assert_eq!(result, Ok(()));
+        } else {
+            assert!(result.is_err());
+        }
+    }
+}
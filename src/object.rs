@@ -0,0 +1,240 @@
+//! Minimal readers for the object file formats Lute is built into (ELF,
+//! Mach-O and COFF), just enough for the checks of the `archive` and `musl`
+//! modules: the symbols an object file defines and the program headers of an
+//! ELF executable.
+
+/// Reads the `width` byte unsigned integer at `offset` of `data`
+pub(crate) fn read_uint(data: &[u8], offset: usize, width: usize, little_endian: bool) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(width)?)?;
+    let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+    Some(if little_endian {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    })
+}
+
+/// Reads the NUL terminated string at `offset` of `data`
+pub(crate) fn c_str(data: &[u8], offset: usize) -> Option<&str> {
+    let bytes = data.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    std::str::from_utf8(&bytes[..end]).ok()
+}
+
+/// Returns the global symbols defined by the ELF, Mach-O or COFF (regular or
+/// `/bigobj`) object file `data`, or `None` if it is none of those (e.g. LLVM
+/// bitcode from LTO builds)
+pub(crate) fn defined_symbols(data: &[u8]) -> Option<Vec<String>> {
+    if let Some(elf) = Elf::parse(data) {
+        elf.defined_symbols()
+    } else if data.starts_with(&[0xcf, 0xfa, 0xed, 0xfe]) || data.starts_with(&[0xce, 0xfa, 0xed, 0xfe]) {
+        macho_defined_symbols(data)
+    } else {
+        coff_defined_symbols(data)
+    }
+}
+
+pub(crate) const PT_LOAD: u32 = 1;
+pub(crate) const PT_DYNAMIC: u32 = 2;
+pub(crate) const PT_INTERP: u32 = 3;
+
+const SHT_SYMTAB: u64 = 2;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STB_GNU_UNIQUE: u8 = 10;
+
+pub(crate) struct ProgramHeader {
+    pub kind: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+}
+
+pub(crate) struct Elf<'a> {
+    data: &'a [u8],
+    pub is_64: bool,
+    little_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 52 || &data[..4] != b"\x7fELF" {
+            return None;
+        }
+        Some(Self {
+            data,
+            is_64: data[4] == 2,
+            little_endian: data[5] == 1,
+        })
+    }
+
+    pub fn read(&self, offset: u64, size: usize) -> Option<u64> {
+        read_uint(self.data, usize::try_from(offset).ok()?, size, self.little_endian)
+    }
+
+    /// Reads a pointer-sized value (address, offset or dynamic entry field)
+    pub fn word(&self, offset: u64) -> Option<u64> {
+        self.read(offset, if self.is_64 { 8 } else { 4 })
+    }
+
+    pub fn c_str(&self, offset: u64) -> Option<&'a str> {
+        c_str(self.data, usize::try_from(offset).ok()?)
+    }
+
+    pub fn program_headers(&self) -> Vec<ProgramHeader> {
+        let (phoff, phentsize, phnum) = if self.is_64 {
+            (self.read(0x20, 8), self.read(0x36, 2), self.read(0x38, 2))
+        } else {
+            (self.read(0x1c, 4), self.read(0x2a, 2), self.read(0x2c, 2))
+        };
+        let (Some(phoff), Some(phentsize), Some(phnum)) = (phoff, phentsize, phnum) else {
+            return Vec::new();
+        };
+
+        (0..phnum)
+            .filter_map(|i| {
+                let ph = phoff + i * phentsize;
+                Some(if self.is_64 {
+                    ProgramHeader {
+                        kind: self.read(ph, 4)? as u32,
+                        offset: self.read(ph + 0x08, 8)?,
+                        vaddr: self.read(ph + 0x10, 8)?,
+                        file_size: self.read(ph + 0x20, 8)?,
+                    }
+                } else {
+                    ProgramHeader {
+                        kind: self.read(ph, 4)? as u32,
+                        offset: self.read(ph + 0x04, 4)?,
+                        vaddr: self.read(ph + 0x08, 4)?,
+                        file_size: self.read(ph + 0x10, 4)?,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the global and weak symbols defined in the `.symtab` section
+    pub fn defined_symbols(&self) -> Option<Vec<String>> {
+        let (shoff, shentsize, shnum) = if self.is_64 {
+            (self.read(0x28, 8)?, self.read(0x3a, 2)?, self.read(0x3c, 2)?)
+        } else {
+            (self.read(0x20, 4)?, self.read(0x2e, 2)?, self.read(0x30, 2)?)
+        };
+        // (type, offset, size, link) of section `index`
+        let section = |index: u64| {
+            let sh = shoff + index * shentsize;
+            Some(if self.is_64 {
+                (self.read(sh + 0x04, 4)?, self.read(sh + 0x18, 8)?, self.read(sh + 0x20, 8)?, self.read(sh + 0x28, 4)?)
+            } else {
+                (self.read(sh + 0x04, 4)?, self.read(sh + 0x10, 4)?, self.read(sh + 0x14, 4)?, self.read(sh + 0x18, 4)?)
+            })
+        };
+
+        let mut symbols = Vec::new();
+        for index in 0..shnum {
+            let (kind, offset, size, link) = section(index)?;
+            if kind != SHT_SYMTAB {
+                continue;
+            }
+            let (_, strtab, _, _) = section(link)?;
+            let entry_size = if self.is_64 { 24 } else { 16 };
+            for sym in (offset..offset + size).step_by(entry_size) {
+                let (info, shndx) = if self.is_64 {
+                    (self.read(sym + 4, 1)? as u8, self.read(sym + 6, 2)?)
+                } else {
+                    (self.read(sym + 12, 1)? as u8, self.read(sym + 14, 2)?)
+                };
+                let bind = info >> 4;
+                if shndx == 0 || !matches!(bind, STB_GLOBAL | STB_WEAK | STB_GNU_UNIQUE) {
+                    continue;
+                }
+                if let Some(name) = self.c_str(strtab + self.read(sym, 4)?) {
+                    symbols.push(name.to_string());
+                }
+            }
+        }
+        Some(symbols)
+    }
+}
+
+const LC_SYMTAB: u64 = 2;
+const N_STAB: u8 = 0xe0;
+const N_EXT: u8 = 0x01;
+const N_TYPE: u8 = 0x0e;
+const N_UNDF: u8 = 0x00;
+
+// Little endian only, like every Mach-O target Rust supports
+fn macho_defined_symbols(data: &[u8]) -> Option<Vec<String>> {
+    let is_64 = data[0] == 0xcf;
+    let read = |offset: usize, width: usize| read_uint(data, offset, width, true).map(|v| v as usize);
+
+    let ncmds = read(16, 4)?;
+    let mut cmd = if is_64 { 32 } else { 28 };
+    for _ in 0..ncmds {
+        let (kind, size) = (read(cmd, 4)?, read(cmd + 4, 4)?);
+        if kind as u64 == LC_SYMTAB {
+            let (symoff, nsyms, stroff) = (read(cmd + 8, 4)?, read(cmd + 12, 4)?, read(cmd + 16, 4)?);
+            let entry_size = if is_64 { 16 } else { 12 };
+            let mut symbols = Vec::new();
+            for i in 0..nsyms {
+                let sym = symoff + i * entry_size;
+                let n_type = *data.get(sym + 4)?;
+                if n_type & N_STAB != 0 || n_type & N_EXT == 0 || n_type & N_TYPE == N_UNDF {
+                    continue;
+                }
+                if let Some(name) = c_str(data, stroff + read(sym, 4)?) {
+                    symbols.push(name.to_string());
+                }
+            }
+            return Some(symbols);
+        }
+        cmd += size;
+    }
+    Some(Vec::new())
+}
+
+const COFF_MACHINES: &[u64] = &[0x014c, 0x8664, 0xaa64, 0xa641, 0x01c4];
+const BIGOBJ_CLASS_ID: [u8; 16] = [
+    0xc7, 0xa1, 0xba, 0xd1, 0xee, 0xba, 0xa9, 0x4b, 0xaf, 0x20, 0xfa, 0xf6, 0x6a, 0xa4, 0xdc, 0xb8,
+];
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+
+fn coff_defined_symbols(data: &[u8]) -> Option<Vec<String>> {
+    let read = |offset: usize, width: usize| read_uint(data, offset, width, true).map(|v| v as usize);
+
+    // (symbol table offset, symbol count, record size)
+    let (symtab, nsyms, record) = if data.starts_with(&[0, 0, 0xff, 0xff]) && data.get(12..28)? == BIGOBJ_CLASS_ID {
+        (read(48, 4)?, read(52, 4)?, 20)
+    } else if COFF_MACHINES.contains(&(read(0, 2)? as u64)) {
+        (read(8, 4)?, read(12, 4)?, 18)
+    } else {
+        return None;
+    };
+    let strtab = symtab + nsyms * record;
+
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < nsyms {
+        let sym = symtab + i * record;
+        // The section number is 32-bit for /bigobj, negative for absolute and
+        // debug symbols and 0 for undefined ones
+        let (section, class, aux) = if record == 20 {
+            (read(sym + 12, 4)? as i32, *data.get(sym + 18)?, *data.get(sym + 19)?)
+        } else {
+            (read(sym + 12, 2)? as i16 as i32, *data.get(sym + 16)?, *data.get(sym + 17)?)
+        };
+        if class == IMAGE_SYM_CLASS_EXTERNAL && section > 0 {
+            // Short names are inline, long ones in the string table
+            let name = if read(sym, 4)? == 0 {
+                c_str(data, strtab + read(sym + 4, 4)?).map(str::to_string)
+            } else {
+                let short = data.get(sym..sym + 8)?;
+                let end = short.iter().position(|&b| b == 0).unwrap_or(8);
+                std::str::from_utf8(&short[..end]).ok().map(str::to_string)
+            };
+            symbols.extend(name);
+        }
+        i += 1 + aux as usize;
+    }
+    Some(symbols)
+}
@@ -1,9 +1,18 @@
-use crate::{LConfig, finalize::finalize_build};
+use crate::{LConfig, archive, wasm, finalize::{finalize_build, static_crt}, report, rerun::{rerun_if_changed, write_if_changed}, search::register_lib_dir};
 
 /// Default build script for prebuilt binaries
 pub fn build_prebuilt_default(lcfg: LConfig) {    
     let current_dir = std::env::current_dir().unwrap().display().to_string();
     let target = std::env::var("TARGET").unwrap();
+    let lcfg = lcfg.for_target(&target);
+    if lcfg.build_cli {
+        panic!("LConfig::build_cli requires building Lute from source");
+    }
+    if wasm::is_wasi(&target) {
+        panic!("There is no prebuilt Lute runtime for {target}, build it from source (with the cc backend)");
+    }
+    // Prebuilt lute runtimes use the dynamic CRT, fail early if that can't link
+    let static_crt = static_crt(&lcfg, true);
 
     println!(
         "Current directory: {}",
@@ -17,7 +26,16 @@ pub fn build_prebuilt_default(lcfg: LConfig) {
         panic!("No prebuilt libs found in repo?");
     }
 
+    // Split archives are reassembled into OUT_DIR, searched first so that
+    // copies reassembled into the checked-in directory by earlier versions
+    // of this crate are shadowed
+    let reassembled_path = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lute-prebuilts");
+    std::fs::create_dir_all(&reassembled_path).expect("Failed to create the reassembled prebuilts directory");
+    println!("cargo:rustc-link-search=native={}", reassembled_path.display());
     println!("cargo:rustc-link-search=native={}", static_libs_path);
+    // Cargo checks every file of a directory for changes
+    rerun_if_changed(slp);
+    let reassemble_start = std::time::Instant::now();
     for entry in std::fs::read_dir(&static_libs_path).expect("Failed to read source directory") {  
         let entry = entry.expect("Failed to read entry");
         let src_path = entry.path();
@@ -27,29 +45,169 @@ pub fn build_prebuilt_default(lcfg: LConfig) {
             continue;
         }
 
-        if src_path.is_file() && src_path.extension().map_or(false, |ext| ext == "part1") {
+        if src_path.is_file() && src_path.extension().is_some_and(|ext| ext == "part1") {
             let dst_path = src_path.display().to_string().split(".part").next().unwrap().to_string();
 
             let mut part_number = 1;
             let mut contents = Vec::new();
+            let mut part_sizes = Vec::new();
             loop {
                 let part_file = format!("{}.part{}", dst_path, part_number);
                 if std::path::Path::new(&part_file).exists() {
                     // Append the part to the destination file
-                    contents.extend(
-                        std::fs::read(&part_file).expect("Failed to read part file")
-                    );
+                    let part = std::fs::read(&part_file).expect("Failed to read part file");
+                    reject_lfs_pointer(std::path::Path::new(&part_file), &part);
+                    part_sizes.push((part_file, part.len()));
+                    contents.extend(part);
                     part_number += 1;
                 } else {
                     break; // No more parts found
                 }
             }
+            check_part_sizes(&part_sizes);
+            check_archive(std::path::Path::new(&format!("{dst_path}.part1")), &contents);
 
             // Write the combined contents to the destination file
-            std::fs::write(&dst_path, contents).expect("Failed to write combined file");
+            let file_name = std::path::Path::new(&dst_path).file_name().unwrap();
+            write_if_changed(&reassembled_path.join(file_name), contents);
             continue;
         }
+
+        let is_library = src_path.extension().is_some_and(|ext| ext == "a" || ext == "lib");
+        if src_path.is_file() && is_library && !is_reassembled_output(&src_path) {
+            let contents = std::fs::read(&src_path).expect("Failed to read prebuilt library");
+            reject_lfs_pointer(&src_path, &contents);
+            check_archive(&src_path, &contents);
+        }
     }
 
+    register_lib_dir(&reassembled_path);
+    register_lib_dir(slp);
+    archive::verify_lute_archives(&lcfg, true);
+    report::record_lconfig(&lcfg);
+    report::record_source("prebuilt");
+    report::record_crt(static_crt);
+    report::record_phase("reassemble", reassemble_start.elapsed());
+    report::write();
+
     finalize_build(lcfg, true);
-}
\ No newline at end of file
+}
+
+// Panics if `contents` (read from `path`) is a Git LFS pointer rather than the
+// file it stands for, i.e. the repository was cloned without `git lfs pull`
+fn reject_lfs_pointer(path: &std::path::Path, contents: &[u8]) {
+    if contents.starts_with(b"version https://git-lfs.github.com/spec/") {
+        panic!(
+            "{} is a Git LFS pointer, not the prebuilt library: the prebuilts are stored with \
+             Git LFS, run `git lfs install && git lfs pull` in the repository",
+            path.display()
+        );
+    }
+}
+
+// Panics if `contents` is neither an `ar` archive nor a COFF import library,
+// naming `path` (the file or first part it was read from)
+fn check_archive(path: &std::path::Path, contents: &[u8]) {
+    let is_archive = contents.starts_with(b"!<arch>\n") || contents.starts_with(b"!<thin>\n");
+    // IMPORT_OBJECT_HEADER: Sig1 = 0, Sig2 = 0xFFFF
+    let is_import_lib = contents.starts_with(&[0x00, 0x00, 0xFF, 0xFF]);
+    if !is_archive && !is_import_lib {
+        let start = String::from_utf8_lossy(&contents[..contents.len().min(16)]).to_string();
+        panic!(
+            "{} is not a static library (starts with {:?}); the prebuilts directory is corrupt, \
+             check it out again",
+            path.display(),
+            start
+        );
+    }
+}
+
+// Panics if the parts of a split archive are not plausible: all but the last
+// are cut at the same size, and none is empty
+fn check_part_sizes(parts: &[(String, usize)]) {
+    let Some(((_, first_size), rest)) = parts.split_first() else {
+        return;
+    };
+    for (index, (part, size)) in parts.iter().enumerate() {
+        let is_last = index == parts.len() - 1;
+        if *size == 0 || (!is_last && size != first_size) || (is_last && !rest.is_empty() && size > first_size) {
+            panic!(
+                "{} has an implausible size ({} bytes, the first part has {}); the prebuilts \
+                 directory is corrupt or incomplete, check it out again",
+                part, size, first_size
+            );
+        }
+    }
+}
+
+/// Returns true if `path` is a file produced by concatenating `<path>.part*`
+/// files, left in the checked-in directory by earlier versions of this crate
+fn is_reassembled_output(path: &std::path::Path) -> bool {
+    let mut part1 = path.as_os_str().to_owned();
+    part1.push(".part1");
+    std::path::Path::new(&part1).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parts(sizes: &[usize]) -> Vec<(String, usize)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(index, size)| (format!("libLuau.VM.a.part{}", index + 1), *size))
+            .collect()
+    }
+
+    #[test]
+    fn plausible_part_sizes() {
+        check_part_sizes(&[]);
+        check_part_sizes(&parts(&[1234]));
+        check_part_sizes(&parts(&[100, 100, 100]));
+        check_part_sizes(&parts(&[100, 100, 42]));
+    }
+
+    #[test]
+    #[should_panic(expected = "libLuau.VM.a.part2 has an implausible size (90 bytes, the first part has 100)")]
+    fn uneven_parts_are_rejected() {
+        check_part_sizes(&parts(&[100, 90, 42]));
+    }
+
+    #[test]
+    #[should_panic(expected = "libLuau.VM.a.part3 has an implausible size (120 bytes")]
+    fn oversized_last_part_is_rejected() {
+        check_part_sizes(&parts(&[100, 100, 120]));
+    }
+
+    #[test]
+    #[should_panic(expected = "libLuau.VM.a.part2 has an implausible size (0 bytes")]
+    fn empty_part_is_rejected() {
+        check_part_sizes(&parts(&[100, 0]));
+    }
+
+    #[test]
+    fn libraries_are_accepted() {
+        let path = Path::new("libLuau.VM.a");
+        reject_lfs_pointer(path, b"!<arch>\n");
+        check_archive(path, b"!<arch>\n");
+        check_archive(path, b"!<thin>\n");
+        check_archive(Path::new("Luau.VM.lib"), &[0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00]);
+    }
+
+    #[test]
+    #[should_panic(expected = "libLuau.VM.a.part1 is a Git LFS pointer")]
+    fn lfs_pointers_are_rejected() {
+        reject_lfs_pointer(
+            Path::new("libLuau.VM.a.part1"),
+            b"version https://git-lfs.github.com/spec/v1\noid sha256:0123\nsize 1234\n",
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "libLuau.VM.a is not a static library (starts with \"<html>\")")]
+    fn other_files_are_rejected() {
+        check_archive(Path::new("libLuau.VM.a"), b"<html>");
+    }
+}
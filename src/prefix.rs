@@ -0,0 +1,214 @@
+//! Symbol prefixing ([`LConfig::symbol_prefix`]).
+//!
+//! Luau is compiled as C++, so besides the `extern "C"` API (`lua_*`,
+//! `luaL_*`, ...) its internal functions have the same mangled names in every
+//! copy of Luau: renaming the public API in the headers is not enough to link
+//! two copies side by side. Instead every symbol defined by the Luau/Lute
+//! archives is renamed in place once they are built, with `nm` listing the
+//! symbols and `objcopy --redefine-syms` rewriting definitions and references
+//! alike. The tools are `LUTE_NM`/`LUTE_OBJCOPY` if set, else `llvm-nm` and
+//! `llvm-objcopy` (which handle every object format) or `nm`/`objcopy`.
+//!
+//! Which archives were rewritten (and the names renamed) is recorded in
+//! `OUT_DIR/lute-symbol-prefix/state.txt`, so that on incremental builds only
+//! the archives rebuilt since are rewritten, consistently with the others.
+//! Changing or removing the prefix afterwards requires a `cargo clean`.
+//! This requires building from source: prebuilt archives are not rewritten.
+//!
+//! A header `#define`-ing the C API to the prefixed names is written to
+//! `OUT_DIR/lute-symbol-prefix/lute_symbol_prefix.h` for C/C++ consumers, its
+//! path exported as the `symbol_prefix_header`
+//! [cargo metadata](crate#metadata-for-dependents) key; the
+//! `bindgen` feature emits the matching `#[link_name]`s.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::finalize::{LinkKind, static_link_libraries};
+use crate::rerun::{print_once, rerun_if_env_changed};
+use crate::search::find_static_lib;
+use crate::LConfig;
+
+/// Renames the symbols of the Luau/Lute archives of the link list for `lcfg`
+/// (no-op without [`LConfig::symbol_prefix`]) and writes the rename header.
+/// The archives are located through the registered library directories.
+pub fn prefix_lute_archives(lcfg: &LConfig, prebuilt: bool) {
+    let dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lute-symbol-prefix");
+    let state_path = dir.join("state.txt");
+    let Some(prefix) = lcfg.symbol_prefix else {
+        // The archives left over from a prefixed build keep the prefixed
+        // names, which the unprefixed consumer would fail to link against
+        if let Some(previous) = State::read(&state_path).prefix {
+            panic!("LConfig::symbol_prefix was {previous:?} when the archives were built and is now unset, run cargo clean");
+        }
+        return;
+    };
+    if prebuilt {
+        panic!("LConfig::symbol_prefix requires building Lute from source");
+    }
+    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        panic!("LConfig::symbol_prefix must be a non-empty C identifier prefix, got {prefix:?}");
+    }
+
+    let target = std::env::var("TARGET").unwrap();
+    let archives = static_link_libraries(*lcfg, prebuilt)
+        .into_iter()
+        .filter(|lib| lib.kind == LinkKind::Static && (lib.name.starts_with("Luau.") || lib.name.starts_with("Lute.")))
+        .map(|lib| {
+            find_static_lib(&lib.name, &target)
+                .unwrap_or_else(|| panic!("Could not find the {} archive to prefix its symbols", lib.name))
+        })
+        .collect::<Vec<_>>();
+
+    std::fs::create_dir_all(&dir).expect("Failed to create symbol prefix directory");
+    let mut state = State::read(&state_path);
+    if state.prefix.as_deref().is_some_and(|previous| previous != prefix) {
+        panic!("LConfig::symbol_prefix changed since the archives were rewritten, run cargo clean");
+    }
+    state.prefix = Some(prefix.to_string());
+    let pending = archives
+        .iter()
+        .filter(|archive| !state.archives.contains(&archive_stamp(archive)))
+        .collect::<Vec<_>>();
+
+    // Mach-O prepends `_` to every C symbol name
+    let underscore = if target.contains("-apple-") { "_" } else { "" };
+    let nm = tool("LUTE_NM", "llvm-nm", "nm");
+    for archive in &pending {
+        for symbol in defined_symbols(&nm, archive) {
+            let Some(name) = symbol.strip_prefix(underscore) else {
+                continue;
+            };
+            // Reserved names belong to the compiler runtime
+            if name.starts_with("__") && !name.starts_with("__Z") {
+                continue;
+            }
+            state.names.insert(name.to_string());
+        }
+    }
+
+    let map = state
+        .names
+        .iter()
+        .map(|name| format!("{underscore}{name} {underscore}{prefix}{name}\n"))
+        .collect::<String>();
+    let map_path = dir.join("redefine-syms.txt");
+    std::fs::write(&map_path, map).expect("Failed to write the symbol rename map");
+
+    let objcopy = tool("LUTE_OBJCOPY", "llvm-objcopy", "objcopy");
+    for archive in &pending {
+        let status = Command::new(&objcopy)
+            .arg(format!("--redefine-syms={}", map_path.display()))
+            .arg(archive)
+            .status()
+            .unwrap_or_else(|e| panic!("Failed to run {objcopy}: {e}"));
+        if !status.success() {
+            panic!("{} failed to prefix the symbols of {}", objcopy, archive.display());
+        }
+        state.archives.insert(archive_stamp(archive));
+    }
+    state.write(&state_path);
+
+    // The C API: the unmangled names
+    let mut header = String::from(
+        "// Generated by lute-src-rs-common, do not edit\n\
+         #pragma once\n",
+    );
+    for name in state.names.iter().filter(|name| !name.starts_with('_') && !name.starts_with('?')) {
+        header.push_str(&format!("#define {name} {prefix}{name}\n"));
+    }
+    let header_path = dir.join("lute_symbol_prefix.h");
+    std::fs::write(&header_path, header).expect("Failed to write the symbol prefix header");
+
+    print_once(&format!("cargo:symbol_prefix={prefix}"));
+    print_once(&format!("cargo:symbol_prefix_header={}", header_path.display()));
+}
+
+// What earlier runs did, see the module documentation
+#[derive(Default)]
+struct State {
+    prefix: Option<String>,
+    // See `archive_stamp`
+    archives: BTreeSet<String>,
+    // Names (without the Mach-O underscore) renamed so far
+    names: BTreeSet<String>,
+}
+
+impl State {
+    fn read(path: &Path) -> Self {
+        let mut state = State::default();
+        for line in std::fs::read_to_string(path).unwrap_or_default().lines() {
+            match line.split_once(' ') {
+                Some(("prefix", prefix)) => state.prefix = Some(prefix.to_string()),
+                Some(("archive", stamp)) => {
+                    state.archives.insert(stamp.to_string());
+                }
+                Some(("symbol", name)) => {
+                    state.names.insert(name.to_string());
+                }
+                _ => {}
+            }
+        }
+        state
+    }
+
+    fn write(&self, path: &Path) {
+        let mut contents = String::new();
+        if let Some(prefix) = &self.prefix {
+            contents.push_str(&format!("prefix {prefix}\n"));
+        }
+        for stamp in &self.archives {
+            contents.push_str(&format!("archive {stamp}\n"));
+        }
+        for name in &self.names {
+            contents.push_str(&format!("symbol {name}\n"));
+        }
+        std::fs::write(path, contents).expect("Failed to write the symbol prefix state");
+    }
+}
+
+// Identifies the current contents of `archive` (size and FNV-1a hash, a
+// timestamp would not tell a rewritten archive which was merely touched)
+fn archive_stamp(archive: &Path) -> String {
+    let data = std::fs::read(archive).unwrap_or_else(|e| panic!("Failed to read {}: {}", archive.display(), e));
+    let hash = data
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    format!("{} {:016x} {}", data.len(), hash, archive.display())
+}
+
+// Returns the first of `env` (if set), `preferred` and `fallback` which runs
+fn tool(env: &str, preferred: &str, fallback: &str) -> String {
+    rerun_if_env_changed(env);
+    if let Ok(tool) = std::env::var(env) {
+        return tool;
+    }
+    let runs = Command::new(preferred).arg("--version").output().is_ok_and(|o| o.status.success());
+    if runs { preferred } else { fallback }.to_string()
+}
+
+// Lists the global symbols defined in `archive`
+fn defined_symbols(nm: &str, archive: &Path) -> Vec<String> {
+    let output = Command::new(nm)
+        .args(["-g", "--defined-only", "-P"])
+        .arg(archive)
+        .output()
+        .unwrap_or_else(|e| panic!("Failed to run {nm}: {e}"));
+    if !output.status.success() {
+        panic!(
+            "{} failed to list the symbols of {}: {}",
+            nm,
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // `name type [value size]`, plus `archive[member]:` lines
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.ends_with(':'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,240 @@
+//! Machine-readable report (`lute-build-report.json` in `OUT_DIR`) of what a
+//! Lute build actually produced.
+//!
+//! Every build entry point (`setup_lute_cmake`, `build_prebuilt_default`,
+//! `finalize_build`) records what it knows and rewrites the report, whose path
+//! is exported as the `build_report`
+//! [cargo metadata](crate#metadata-for-dependents) key (`DEP_LUTE_BUILD_REPORT`).
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::LConfig;
+use crate::finalize::{LinkKind, LinkLib};
+use crate::json::Json;
+use crate::search::find_file;
+
+/// Name of the report file written into `OUT_DIR`
+pub const REPORT_FILE_NAME: &str = "lute-build-report.json";
+
+struct BuildReport {
+    lconfig: Option<Json>,
+    source: Option<&'static str>,
+    static_crt: Option<bool>,
+    cmake: Option<Json>,
+    phases: Vec<(String, Duration)>,
+    libraries: Vec<LinkLib>,
+    shared_library: Option<PathBuf>,
+    metadata_emitted: bool,
+}
+
+static REPORT: Mutex<BuildReport> = Mutex::new(BuildReport {
+    lconfig: None,
+    source: None,
+    static_crt: None,
+    cmake: None,
+    phases: Vec::new(),
+    libraries: Vec::new(),
+    shared_library: None,
+    metadata_emitted: false,
+});
+
+/// Returns the path of the report for the current build script
+pub fn report_path() -> Option<PathBuf> {
+    std::env::var_os("OUT_DIR").map(|out_dir| PathBuf::from(out_dir).join(REPORT_FILE_NAME))
+}
+
+pub(crate) fn record_lconfig(lcfg: &LConfig) {
+    REPORT.lock().unwrap().lconfig = Some(lconfig_json(lcfg));
+}
+
+/// Records where the libraries came from (`cmake` or `prebuilt`)
+pub(crate) fn record_source(source: &'static str) {
+    REPORT.lock().unwrap().source = Some(source);
+}
+
+pub(crate) fn record_crt(static_crt: bool) {
+    REPORT.lock().unwrap().static_crt = Some(static_crt);
+}
+
+pub(crate) fn record_phase(name: &str, elapsed: Duration) {
+    REPORT
+        .lock()
+        .unwrap()
+        .phases
+        .push((name.to_string(), elapsed));
+}
+
+/// Records the generator, CMake version, compilers and build type resolved
+/// by CMake, as found in the `CMakeCache.txt` of `build_dir`
+#[cfg(feature = "full")]
+pub(crate) fn record_cmake_build(build_dir: &std::path::Path) {
+    let cache = std::fs::read_to_string(build_dir.join("CMakeCache.txt")).unwrap_or_default();
+    let lookup = |key: &str| {
+        cache.lines().find_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            let (name, _ty) = name.split_once(':').unwrap_or((name, ""));
+            (name == key).then(|| value.to_string())
+        })
+    };
+
+    let version = match (
+        lookup("CMAKE_CACHE_MAJOR_VERSION"),
+        lookup("CMAKE_CACHE_MINOR_VERSION"),
+        lookup("CMAKE_CACHE_PATCH_VERSION"),
+    ) {
+        (Some(major), Some(minor), Some(patch)) => Some(format!("{major}.{minor}.{patch}")),
+        _ => None,
+    };
+
+    let cmake = Json::object([
+        ("build_dir", Json::str(build_dir.display().to_string())),
+        ("generator", Json::opt_str(lookup("CMAKE_GENERATOR"))),
+        ("version", Json::opt_str(version)),
+        ("build_type", Json::opt_str(lookup("CMAKE_BUILD_TYPE"))),
+        ("c_compiler", Json::opt_str(lookup("CMAKE_C_COMPILER"))),
+        ("cxx_compiler", Json::opt_str(lookup("CMAKE_CXX_COMPILER"))),
+        ("toolchain_file", Json::opt_str(lookup("CMAKE_TOOLCHAIN_FILE"))),
+    ]);
+    REPORT.lock().unwrap().cmake = Some(cmake);
+}
+
+/// Records the shared runtime library which has to be shipped with the consumer
+#[cfg(feature = "full")]
+pub(crate) fn record_shared_library(path: &std::path::Path) {
+    REPORT.lock().unwrap().shared_library = Some(path.to_path_buf());
+}
+
+pub(crate) fn record_link_libraries(libs: &[LinkLib]) {
+    REPORT.lock().unwrap().libraries = libs.to_vec();
+}
+
+/// Writes the report into `OUT_DIR`, returning its path.
+///
+/// Does nothing (and returns `None`) outside of a build script.
+pub(crate) fn write() -> Option<PathBuf> {
+    let path = report_path()?;
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let mut report = REPORT.lock().unwrap();
+
+    let libraries = report
+        .libraries
+        .iter()
+        .map(|lib| {
+            let path = match lib.kind {
+                LinkKind::Static => find_file(&lib.file_name(&target)),
+                LinkKind::Dylib | LinkKind::Default => None,
+            };
+            let size = path
+                .as_ref()
+                .and_then(|p| std::fs::metadata(p).ok())
+                .map_or(Json::Null, |m| Json::Int(m.len()));
+            Json::object([
+                ("link", Json::str(lib.to_string())),
+                ("name", Json::str(&lib.name)),
+                ("path", Json::opt_str(path.map(|p| p.display().to_string()))),
+                ("size", size),
+            ])
+        })
+        .collect();
+
+    let phases = report
+        .phases
+        .iter()
+        .map(|(name, elapsed)| {
+            Json::object([
+                ("phase", Json::str(name)),
+                ("elapsed_ms", Json::Int(elapsed.as_millis() as u64)),
+            ])
+        })
+        .collect();
+
+    let json = Json::object([
+        ("lconfig", report.lconfig.clone().unwrap_or(Json::Null)),
+        ("target", Json::str(&target)),
+        ("host", Json::opt_str(std::env::var("HOST").ok())),
+        ("cargo_profile", Json::opt_str(std::env::var("PROFILE").ok())),
+        ("source", Json::opt_str(report.source)),
+        (
+            "crt",
+            Json::opt_str(report.static_crt.map(|s| if s { "static" } else { "dynamic" })),
+        ),
+        ("cmake", report.cmake.clone().unwrap_or(Json::Null)),
+        ("phases", Json::Array(phases)),
+        ("libraries", Json::Array(libraries)),
+        (
+            "shared_library",
+            Json::opt_str(report.shared_library.as_ref().map(|p| p.display().to_string())),
+        ),
+    ]);
+
+    if let Err(e) = std::fs::write(&path, json.pretty()) {
+        println!("cargo:warning=failed to write {}: {}", path.display(), e);
+        return None;
+    }
+
+    if !report.metadata_emitted {
+        println!("cargo:build_report={}", path.display());
+        report.metadata_emitted = true;
+    }
+    Some(path)
+}
+
+fn lconfig_json(lcfg: &LConfig) -> Json {
+    Json::object([
+        ("disable_crypto", Json::Bool(lcfg.disable_crypto)),
+        ("disable_net", Json::Bool(lcfg.disable_net)),
+        ("disable_native_codegen", Json::Bool(lcfg.disable_native_codegen)),
+        ("profile", Json::str(format!("{:?}", lcfg.profile))),
+        ("export_compile_commands", Json::Bool(lcfg.export_compile_commands)),
+        ("compile_commands_path", Json::opt_str(lcfg.compile_commands_path)),
+        ("backend", Json::str(format!("{:?}", lcfg.backend))),
+        ("toolchain", Json::str(format!("{:?}", lcfg.toolchain))),
+        ("crt", Json::str(format!("{:?}", lcfg.crt))),
+        ("cpp_stdlib", Json::str(format!("{:?}", lcfg.cpp_stdlib))),
+        ("link_mode", Json::str(format!("{:?}", lcfg.link_mode))),
+        (
+            "link_modifiers",
+            Json::Object(
+                lcfg.link_modifiers
+                    .iter()
+                    .map(|(lib, modifiers)| {
+                        let modifiers = modifiers.iter().map(|m| Json::str(m.to_string()));
+                        (lib.to_string(), Json::Array(modifiers.collect()))
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "system_deps",
+            Json::object([("libuv", Json::str(format!("{:?}", lcfg.system_deps.libuv)))]),
+        ),
+        (
+            "emscripten",
+            Json::object([
+                ("exceptions", Json::str(format!("{:?}", lcfg.emscripten.exceptions))),
+                ("allow_memory_growth", Json::Bool(lcfg.emscripten.allow_memory_growth)),
+                (
+                    "stack_size",
+                    lcfg.emscripten.stack_size.map_or(Json::Null, |size| Json::Int(size as u64)),
+                ),
+                (
+                    "exported_functions",
+                    Json::Array(lcfg.emscripten.exported_functions.iter().map(Json::str).collect()),
+                ),
+            ]),
+        ),
+        (
+            "unity_build",
+            lcfg.unity_build.map_or(Json::Null, |unity| {
+                Json::object([
+                    ("batch_size", Json::Int(unity.batch_size as u64)),
+                    ("exclude", Json::Array(unity.exclude.iter().map(Json::str).collect())),
+                ])
+            }),
+        ),
+        ("symbol_prefix", Json::opt_str(lcfg.symbol_prefix)),
+        ("build_cli", Json::Bool(lcfg.build_cli)),
+    ])
+}
@@ -0,0 +1,61 @@
+//! Helpers for emitting `cargo:rerun-if-changed` / `cargo:rerun-if-env-changed`
+//! directives (and other directives which should only be printed once).
+//!
+//! Once a build script emits a single `rerun-if-changed` directive, cargo stops
+//! rerunning it on every change to the package and only watches the listed
+//! paths, so every input the Lute build reads needs to be reported here.
+//! Conversely, generated files are only rewritten when their contents change
+//! (`write_if_changed`), so that their timestamps don't trigger rebuilds.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+static EMITTED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// Returns true the first time `directive` is seen in this build script run
+fn first_emit(directive: &str) -> bool {
+    let mut emitted = EMITTED.lock().unwrap();
+    emitted
+        .get_or_insert_with(HashSet::new)
+        .insert(directive.to_string())
+}
+
+/// Tells cargo to rerun the build script when the file or directory at `path` changes.
+///
+/// Directories are scanned recursively by cargo.
+pub fn rerun_if_changed<P: AsRef<Path>>(path: P) {
+    let directive = format!("cargo:rerun-if-changed={}", path.as_ref().display());
+    if first_emit(&directive) {
+        println!("{directive}");
+    }
+}
+
+/// Tells cargo to rerun the build script when the environment variable `var` changes.
+pub fn rerun_if_env_changed(var: &str) {
+    let directive = format!("cargo:rerun-if-env-changed={var}");
+    if first_emit(&directive) {
+        println!("{directive}");
+    }
+}
+
+/// Prints `directive` (e.g. `cargo:key=value`), once per build script run
+pub(crate) fn print_once(directive: &str) {
+    if first_emit(directive) {
+        println!("{directive}");
+    }
+}
+
+/// Emits `message` as a `cargo:warning`, once per build script run
+pub(crate) fn warn_once(message: &str) {
+    print_once(&format!("cargo:warning={message}"));
+}
+
+/// Writes `contents` to `path` unless it already holds them, keeping the
+/// timestamp of an unchanged file stable
+pub(crate) fn write_if_changed<C: AsRef<[u8]>>(path: &Path, contents: C) {
+    let contents = contents.as_ref();
+    if std::fs::read(path).ok().as_deref() != Some(contents) {
+        std::fs::write(path, contents).unwrap_or_else(|e| panic!("Failed to write {}: {}", path.display(), e));
+    }
+}
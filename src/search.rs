@@ -0,0 +1,90 @@
+//! Tracks the directories Lute static libraries were produced into (by
+//! `setup_lute_cmake` or `build_prebuilt_default`) and locates the archives
+//! named in the link list within them.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+struct LibDirs {
+    dirs: Vec<PathBuf>,
+    // file name -> first path found, rebuilt lazily (see `find_file`)
+    index: Option<HashMap<String, PathBuf>>,
+}
+
+static LIB_DIRS: Mutex<LibDirs> = Mutex::new(LibDirs {
+    dirs: Vec::new(),
+    index: None,
+});
+
+/// Registers a directory (searched recursively) containing built Lute libraries
+pub fn register_lib_dir<P: AsRef<Path>>(dir: P) {
+    let mut lib_dirs = LIB_DIRS.lock().unwrap();
+    let dir = dir.as_ref().to_path_buf();
+    if !lib_dirs.dirs.contains(&dir) {
+        lib_dirs.dirs.push(dir);
+        lib_dirs.index = None;
+    }
+}
+
+/// Returns the directories registered through [`register_lib_dir`]
+pub fn lib_dirs() -> Vec<PathBuf> {
+    LIB_DIRS.lock().unwrap().dirs.clone()
+}
+
+/// Returns the file name of the static library `name` for `target`
+/// (`libfoo.a` or `foo.lib` on MSVC)
+pub fn static_lib_file_name(name: &str, target: &str) -> String {
+    if target.contains("msvc") {
+        format!("{name}.lib")
+    } else {
+        format!("lib{name}.a")
+    }
+}
+
+/// Locates the static library `name` in the registered directories
+pub fn find_static_lib(name: &str, target: &str) -> Option<PathBuf> {
+    find_file(&static_lib_file_name(name, target))
+}
+
+/// Locates a file named `file_name` in the registered directories.
+///
+/// The directories are indexed once, but files may be created in them later
+/// (e.g. by the shared library or symbol prefix steps): the index is rebuilt
+/// when it misses `file_name` or points to a file which no longer exists.
+pub fn find_file(file_name: &str) -> Option<PathBuf> {
+    let mut lib_dirs = LIB_DIRS.lock().unwrap();
+    let cached = lib_dirs
+        .index
+        .as_ref()
+        .and_then(|index| index.get(file_name))
+        .filter(|path| path.is_file());
+    if let Some(path) = cached {
+        return Some(path.clone());
+    }
+
+    let mut index = HashMap::new();
+    for dir in &lib_dirs.dirs {
+        index_dir(dir, &mut index);
+    }
+    let found = index.get(file_name).cloned();
+    lib_dirs.index = Some(index);
+    found
+}
+
+fn index_dir(dir: &Path, index: &mut HashMap<String, PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            index_dir(&path, index);
+        } else if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            index.entry(name.to_string()).or_insert(path);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Shared library mode ([`LinkMode::Shared`](crate::LinkMode::Shared)).
+//!
+//! The runtime is first built as the usual set of static libraries (with
+//! position independent code), which are then linked as whole archives into a
+//! single `liblute.so`/`liblute.dylib`/`lute.dll` so that every Rust cdylib
+//! linking it shares one runtime instance.
+//!
+//! Link arguments printed by a build script only apply to the binaries of its
+//! own package, so the executables (or cdylibs) loading the library must get
+//! its rpath from their own build script, from the `rpath`
+//! [cargo metadata](crate#metadata-for-dependents) key (`DEP_LUTE_RPATH`):
+//!
+//! ```no_run
+//! if let Ok(rpath) = std::env::var("DEP_LUTE_RPATH") {
+//!     println!("cargo:rustc-link-arg=-Wl,-rpath,{rpath}");
+//! }
+//! ```
+//!
+//! and the library itself (`DEP_LUTE_SHARED_LIB`) has to be copied next to
+//! them.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+use crate::finalize::{LinkKind, SHARED_LIB_NAME, static_link_libraries};
+use crate::search::find_static_lib;
+use crate::{CppStdlib, LConfig, prefix, report, sysdeps, toolchain};
+
+/// Returns the file name of the shared runtime library for `target`
+pub fn shared_lib_file_name(target: &str) -> String {
+    if target.contains("windows") {
+        format!("{SHARED_LIB_NAME}.dll")
+    } else if target.contains("apple") {
+        format!("lib{SHARED_LIB_NAME}.dylib")
+    } else {
+        format!("lib{SHARED_LIB_NAME}.so")
+    }
+}
+
+/// Returns the rpath the binaries loading the shared runtime library need to
+/// find it next to them (Windows searches the executable's directory anyway)
+pub fn rpath(target: &str) -> Option<&'static str> {
+    if target.contains("apple") {
+        Some("@loader_path")
+    } else if target.contains("windows") {
+        None
+    } else {
+        Some("$ORIGIN")
+    }
+}
+
+/// Links the static libraries previously built for `lcfg` (and registered with
+/// the `search` module) into the shared runtime library, returning its path.
+///
+/// This emits the link search path and exports the path of the library and
+/// its rpath as the `shared_lib` and `rpath`
+/// [cargo metadata](crate#metadata-for-dependents) keys, see the module
+/// documentation.
+pub fn link_shared_runtime(lcfg: LConfig) -> PathBuf {
+    let target = std::env::var("TARGET").unwrap();
+    if target.contains("msvc") {
+        panic!(
+            "LinkMode::Shared is not supported on MSVC targets (Lute does not annotate its \
+             API for dllexport), use a windows-gnu target or LinkMode::Static"
+        );
+    }
+    if target.contains("wasm") {
+        panic!("LinkMode::Shared is not supported on wasm targets");
+    }
+
+    let start = Instant::now();
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("lute-shared");
+    std::fs::create_dir_all(&out_dir).expect("Failed to create shared library output directory");
+    let shared_lib = out_dir.join(shared_lib_file_name(&target));
+
+    prefix::prefix_lute_archives(&lcfg, false);
+    let mut archives = Vec::new();
+    let mut system_libs = Vec::new();
+    for lib in static_link_libraries(lcfg, false) {
+        match lib.kind {
+            LinkKind::Static => archives.push(
+                find_static_lib(&lib.name, &target)
+                    .unwrap_or_else(|| panic!("Could not find the {} static library to link into {}", lib.name, shared_lib.display())),
+            ),
+            LinkKind::Dylib | LinkKind::Default => system_libs.push(lib.name),
+        }
+    }
+
+    let mut build = cc::Build::new();
+    build.cpp(true).cargo_metadata(false).warnings(false);
+    toolchain::configure_cc(&lcfg, &mut build, true);
+    let compiler = build.get_compiler();
+    let mut cmd = compiler.to_command();
+    cmd.arg("-o").arg(&shared_lib);
+
+    if target.contains("apple") {
+        cmd.arg("-dynamiclib")
+            .arg(format!("-Wl,-install_name,@rpath/{}", shared_lib_file_name(&target)));
+        for archive in &archives {
+            cmd.arg(format!("-Wl,-force_load,{}", archive.display()));
+        }
+    } else {
+        cmd.arg("-shared");
+        if target.contains("windows") {
+            // Export everything and produce the import library rustc links against
+            let implib = out_dir.join(format!("lib{SHARED_LIB_NAME}.dll.a"));
+            cmd.arg("-Wl,--export-all-symbols")
+                .arg(format!("-Wl,--out-implib,{}", implib.display()));
+        } else {
+            cmd.arg(format!("-Wl,-soname,{}", shared_lib_file_name(&target)));
+        }
+        cmd.arg("-Wl,--whole-archive")
+            .args(&archives)
+            .arg("-Wl,--no-whole-archive");
+    }
+
+    match lcfg.cpp_stdlib {
+        CppStdlib::StaticLibStdCxx => {
+            cmd.arg("-static-libstdc++");
+        }
+        CppStdlib::LibCxx if compiler.is_like_clang() => {
+            cmd.arg("-stdlib=libc++");
+        }
+        _ => {}
+    }
+
+    for path in sysdeps::link_search_paths(&lcfg) {
+        cmd.arg(format!("-L{}", path.display()));
+    }
+    for lib in &system_libs {
+        cmd.arg(format!("-l{lib}"));
+    }
+
+    run_link(&mut cmd, &shared_lib);
+
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    if let Some(rpath) = rpath(&target) {
+        // Only reaches this package's own tests and binaries
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{rpath}");
+        println!("cargo:rpath={rpath}");
+    }
+    println!("cargo:shared_lib={}", shared_lib.display());
+
+    report::record_phase("link shared", start.elapsed());
+    report::record_shared_library(&shared_lib);
+    shared_lib
+}
+
+fn run_link(cmd: &mut Command, output: &Path) {
+    println!("running: {:?}", cmd);
+    let status = cmd
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run the linker for {}: {}", output.display(), e));
+    if !status.success() {
+        panic!("Linking {} failed: {}", output.display(), status);
+    }
+}
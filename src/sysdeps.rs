@@ -0,0 +1,206 @@
+//! System installed libuv ([`DepSource::System`]).
+//!
+//! Distribution packagers usually have to link against the system copy of
+//! libuv rather than the one vendored in the lute tree. It is located through
+//! `pkg-config`, its include directories come first in the `cc` builds of the
+//! runtime and the link list gets the libraries `pkg-config` reports instead
+//! of the bundled archive.
+//!
+//! With [`DepSource::Crate`], the copy built by a libuv `-sys` crate is used
+//! instead: its headers are found through `DEP_UV_INCLUDE` (or
+//! `DEP_UV_ROOT`), and nothing is added to the link list as the `-sys` crate
+//! links it already.
+//!
+//! Lute's CMake project always builds and links the bundled copies, so both
+//! require [`Backend::Cc`], where this crate controls the include paths and
+//! the libraries built. That backend has no net and crypto modules, so the
+//! other third party dependencies (zlib, curl, BoringSSL and libsodium) are
+//! always the bundled ones.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::rerun::rerun_if_env_changed;
+use crate::{Backend, DepSource, LConfig, Profile};
+
+/// A library found through `pkg-config` or the `DEP_*` metadata of a `-sys`
+/// crate
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemLibrary {
+    /// Libraries to link (`-l`), in order (none for [`DepSource::Crate`])
+    pub libs: Vec<String>,
+    /// Library search paths (`-L`)
+    pub link_paths: Vec<PathBuf>,
+    /// Include directories (`-I`)
+    pub include_paths: Vec<PathBuf>,
+}
+
+// The result of the first lookup, if any
+static PROBED: Mutex<Option<Option<SystemLibrary>>> = Mutex::new(None);
+
+/// Returns true if the runtime built for `lcfg` needs libuv
+pub fn uses_libuv(lcfg: &LConfig) -> bool {
+    lcfg.profile == Profile::Full
+}
+
+/// Returns the system (or `-sys` crate) copy of libuv if `lcfg` uses libuv,
+/// asks for that copy and it is found.
+///
+/// When it is not found, a warning is emitted (once) and `None` is returned:
+/// the bundled copy is used instead.
+pub fn system_libuv(lcfg: &LConfig) -> Option<SystemLibrary> {
+    let source = lcfg.system_deps.libuv;
+    if !uses_libuv(lcfg) || source == DepSource::Bundled {
+        return None;
+    }
+
+    let mut probed = PROBED.lock().unwrap();
+    if let Some(library) = probed.as_ref() {
+        return library.clone();
+    }
+
+    let result = match source {
+        DepSource::Crate => crate_library("uv"),
+        _ => probe("libuv"),
+    };
+    let library = match result {
+        Ok(library) => Some(library),
+        Err(e) => {
+            println!(
+                "cargo:warning={} libuv requested but not usable ({}), falling back to the bundled copy",
+                if source == DepSource::Crate { "Crate provided" } else { "System" },
+                e
+            );
+            None
+        }
+    };
+    *probed = Some(library.clone());
+    library
+}
+
+/// Returns the effective source of libuv for `lcfg`, after falling back to
+/// the bundled copy if it could not be found
+pub fn libuv_source(lcfg: &LConfig) -> DepSource {
+    match system_libuv(lcfg) {
+        Some(_) => lcfg.system_deps.libuv,
+        None => DepSource::Bundled,
+    }
+}
+
+/// Panics if `lcfg` takes libuv from the system or a `-sys` crate while the
+/// runtime is built with CMake or prebuilt, which always use the bundled
+/// copy (see the module documentation)
+pub(crate) fn check_sources(lcfg: &LConfig, prebuilt: bool) {
+    let source = lcfg.system_deps.libuv;
+    if source == DepSource::Bundled || !uses_libuv(lcfg) {
+        return;
+    }
+    if prebuilt {
+        panic!(
+            "LConfig::system_deps: libuv from {source:?} requires building Lute from source, the prebuilt \
+             runtime is compiled against the bundled copy"
+        );
+    }
+    if lcfg.backend != Backend::Cc {
+        panic!(
+            "LConfig::system_deps: libuv from {source:?} requires Backend::Cc, lute's CMake project always \
+             builds the bundled copy"
+        );
+    }
+}
+
+/// Returns the library search paths of the system libuv used by `lcfg`
+pub fn link_search_paths(lcfg: &LConfig) -> Vec<PathBuf> {
+    system_libuv(lcfg).map(|l| l.link_paths).unwrap_or_default()
+}
+
+/// Returns the include directories of the system libuv used by `lcfg`
+pub fn include_paths(lcfg: &LConfig) -> Vec<PathBuf> {
+    system_libuv(lcfg).map(|l| l.include_paths).unwrap_or_default()
+}
+
+// Reads the `DEP_<links>_ROOT` and `DEP_<links>_INCLUDE` metadata of the
+// `-sys` crate with `links = "<links>"`
+fn crate_library(links: &str) -> Result<SystemLibrary, String> {
+    let links = links.to_uppercase();
+    let root_var = format!("DEP_{links}_ROOT");
+    let include_var = format!("DEP_{links}_INCLUDE");
+    rerun_if_env_changed(&root_var);
+    rerun_if_env_changed(&include_var);
+
+    let root = std::env::var_os(&root_var).map(PathBuf::from);
+    let include_paths: Vec<PathBuf> = match std::env::var_os(&include_var) {
+        Some(include) => std::env::split_paths(&include).collect(),
+        None => root.iter().map(|root| root.join("include")).collect(),
+    };
+    if include_paths.is_empty() {
+        return Err(format!(
+            "neither {root_var} nor {include_var} is set, is the -sys crate a dependency of this crate?"
+        ));
+    }
+
+    Ok(SystemLibrary {
+        include_paths,
+        ..Default::default()
+    })
+}
+
+// Runs `pkg-config --libs --cflags` for `package` (honoring the same
+// environment variables as the `pkg-config` crate)
+fn probe(package: &str) -> Result<SystemLibrary, String> {
+    for var in [
+        "PKG_CONFIG",
+        "PKG_CONFIG_PATH",
+        "PKG_CONFIG_LIBDIR",
+        "PKG_CONFIG_SYSROOT_DIR",
+        "PKG_CONFIG_ALLOW_CROSS",
+        "PKG_CONFIG_ALL_STATIC",
+    ] {
+        rerun_if_env_changed(var);
+    }
+
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let host = std::env::var("HOST").unwrap_or_default();
+    if target != host
+        && std::env::var("PKG_CONFIG_ALLOW_CROSS").as_deref() != Ok("1")
+        && std::env::var_os("PKG_CONFIG_SYSROOT_DIR").is_none()
+    {
+        return Err(
+            "pkg-config is not configured for cross compilation, set PKG_CONFIG_SYSROOT_DIR or PKG_CONFIG_ALLOW_CROSS=1"
+                .to_string(),
+        );
+    }
+
+    let pkg_config = std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string());
+    let mut cmd = Command::new(&pkg_config);
+    cmd.arg("--libs").arg("--cflags");
+    if std::env::var("PKG_CONFIG_ALL_STATIC").as_deref() == Ok("1") {
+        cmd.arg("--static");
+    }
+    cmd.arg(package);
+
+    let output = cmd
+        .output()
+        .map_err(|e| format!("failed to run {pkg_config}: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} --libs --cflags {} failed: {}",
+            pkg_config,
+            package,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut library = SystemLibrary::default();
+    for token in String::from_utf8_lossy(&output.stdout).split_whitespace() {
+        if let Some(lib) = token.strip_prefix("-l") {
+            library.libs.push(lib.to_string());
+        } else if let Some(path) = token.strip_prefix("-L") {
+            library.link_paths.push(PathBuf::from(path));
+        } else if let Some(path) = token.strip_prefix("-I") {
+            library.include_paths.push(PathBuf::from(path));
+        }
+    }
+    Ok(library)
+}
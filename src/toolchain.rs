@@ -0,0 +1,265 @@
+//! Compiler toolchains for building Lute from source ([`Toolchain`]).
+//!
+//! With [`Toolchain::Zig`], every target is built with `zig cc`/`zig c++`, so
+//! that Linux CI can cross compile to e.g. aarch64 musl or windows-gnu without
+//! installing a GCC toolchain per target. Wrapper scripts calling zig with the
+//! right `-target` are generated into `OUT_DIR/zig-toolchain`, together with a
+//! CMake toolchain file using them; `cc` builds get the same wrappers.
+//!
+//! Only the Lute build goes through zig: the final link is still done by
+//! rustc's linker, so cross compiling also needs `CARGO_TARGET_<TRIPLE>_LINKER`
+//! pointing at a zig based linker (e.g. the generated `zig-cc` wrapper, whose
+//! path is exported as the `zig_cc`
+//! [cargo metadata](crate#metadata-for-dependents) key) or cargo-zigbuild.
+
+use std::path::{Path, PathBuf};
+
+use crate::rerun::{print_once, rerun_if_env_changed, write_if_changed};
+use crate::{LConfig, Toolchain, musl};
+
+/// A CMake toolchain file
+#[derive(Clone, Debug, Default)]
+pub struct ToolchainFile {
+    /// `CMAKE_SYSTEM_NAME` and `CMAKE_SYSTEM_PROCESSOR`
+    pub system: Option<(String, String)>,
+    pub c_compiler: Option<PathBuf>,
+    pub cxx_compiler: Option<PathBuf>,
+    pub ar: Option<PathBuf>,
+    pub ranlib: Option<PathBuf>,
+    /// `CMAKE_SYSROOT`
+    pub sysroot: Option<PathBuf>,
+    /// When set, `CMAKE_FIND_ROOT_PATH` (the sysroot is searched as well) and
+    /// the `CMAKE_FIND_ROOT_PATH_MODE_*` variables restricting libraries,
+    /// headers and packages to those roots while programs come from the host
+    pub find_root_path: Option<Vec<PathBuf>>,
+}
+
+impl ToolchainFile {
+    /// Returns the contents of the toolchain file
+    pub fn contents(&self) -> String {
+        let mut contents = String::from("# Generated by lute-src-rs-common, do not edit\n");
+        if let Some((name, processor)) = &self.system {
+            contents.push_str(&format!("set(CMAKE_SYSTEM_NAME {name})\n"));
+            contents.push_str(&format!("set(CMAKE_SYSTEM_PROCESSOR {processor})\n"));
+        }
+        let tools = [
+            ("CMAKE_C_COMPILER", &self.c_compiler, false),
+            ("CMAKE_CXX_COMPILER", &self.cxx_compiler, false),
+            // CMake only picks the archiver up from the cache
+            ("CMAKE_AR", &self.ar, true),
+            ("CMAKE_RANLIB", &self.ranlib, true),
+        ];
+        for (var, path, cache) in tools {
+            let Some(path) = path else {
+                continue;
+            };
+            let path = cmake_path(path);
+            if cache {
+                contents.push_str(&format!("set({var} \"{path}\" CACHE FILEPATH \"\" FORCE)\n"));
+            } else {
+                contents.push_str(&format!("set({var} \"{path}\")\n"));
+            }
+        }
+        if let Some(sysroot) = &self.sysroot {
+            contents.push_str(&format!("set(CMAKE_SYSROOT \"{}\")\n", cmake_path(sysroot)));
+        }
+        if let Some(find_root_path) = &self.find_root_path {
+            let paths = find_root_path.iter().map(|p| cmake_path(p)).collect::<Vec<_>>();
+            contents.push_str(&format!("set(CMAKE_FIND_ROOT_PATH \"{}\")\n", paths.join(";")));
+            contents.push_str(
+                "set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)\n\
+                 set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY ONLY)\n\
+                 set(CMAKE_FIND_ROOT_PATH_MODE_INCLUDE ONLY)\n\
+                 set(CMAKE_FIND_ROOT_PATH_MODE_PACKAGE ONLY)\n",
+            );
+        }
+        contents
+    }
+
+    /// Writes the toolchain file to `path` (only touching it if its contents
+    /// changed, as it is an input of the configure step)
+    pub fn write(&self, path: &Path) {
+        write_if_changed(path, self.contents());
+    }
+}
+
+/// The zig wrappers generated for a target
+#[derive(Clone, Debug)]
+pub struct ZigToolchain {
+    pub cc: PathBuf,
+    pub cxx: PathBuf,
+    pub ar: PathBuf,
+    pub ranlib: PathBuf,
+    pub toolchain_file: PathBuf,
+}
+
+/// Maps a rust target triple to the zig one, if zig supports the target
+pub fn zig_target(rust_target: &str) -> Option<String> {
+    let parts = rust_target.split('-').collect::<Vec<_>>();
+    let arch = match parts[0] {
+        "i586" | "i686" => "x86",
+        "armv7" | "arm" | "thumbv7neon" => "arm",
+        "riscv64gc" => "riscv64",
+        arch => arch,
+    };
+    let env = *parts.last()?;
+
+    if rust_target.contains("-apple-darwin") {
+        Some(format!("{arch}-macos"))
+    } else if rust_target.contains("-windows-gnu") {
+        Some(format!("{arch}-windows-gnu"))
+    } else if rust_target.contains("-linux-") && !rust_target.contains("android") {
+        Some(format!("{arch}-linux-{env}"))
+    } else if rust_target.contains("-freebsd") {
+        Some(format!("{arch}-freebsd"))
+    } else if rust_target.starts_with("wasm32-wasi") {
+        Some("wasm32-wasi".to_string())
+    } else {
+        None
+    }
+}
+
+/// Generates the zig wrappers and toolchain file for `target` into
+/// `OUT_DIR/zig-toolchain`.
+///
+/// The zig executable is taken from `ZIG` (default: `zig` in `PATH`), and the
+/// zig target from `LUTE_ZIG_TARGET` if set (e.g. to pin a glibc version with
+/// `x86_64-linux-gnu.2.28`).
+pub fn zig_toolchain(target: &str) -> ZigToolchain {
+    if cfg!(windows) {
+        panic!("Toolchain::Zig requires a Unix host (the zig wrappers are shell scripts)");
+    }
+
+    rerun_if_env_changed("ZIG");
+    rerun_if_env_changed("LUTE_ZIG_TARGET");
+    let zig = std::env::var("ZIG").unwrap_or_else(|_| "zig".to_string());
+    let zig_target = std::env::var("LUTE_ZIG_TARGET")
+        .ok()
+        .or_else(|| zig_target(target))
+        .unwrap_or_else(|| {
+            panic!("Toolchain::Zig does not know the zig target for {target}, set LUTE_ZIG_TARGET")
+        });
+
+    let dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("zig-toolchain");
+    std::fs::create_dir_all(&dir).expect("Failed to create zig toolchain directory");
+
+    let wrapper = |name: &str, command: &str| {
+        // Drop the `--target=<rust triple>` cc adds for clang-like compilers,
+        // zig only understands its own `-target`
+        let script = format!(
+            "#!/bin/sh\n\
+             # Generated by lute-src-rs-common, do not edit\n\
+             for arg; do\n  shift\n  case \"$arg\" in\n    --target=*) ;;\n    *) set -- \"$@\" \"$arg\" ;;\n  esac\ndone\n\
+             exec \"{zig}\" {command} \"$@\"\n"
+        );
+        let path = dir.join(name);
+        write_if_changed(&path, &script);
+        make_executable(&path);
+        path
+    };
+
+    let toolchain = ZigToolchain {
+        cc: wrapper("zig-cc", &format!("cc -target {zig_target}")),
+        cxx: wrapper("zig-c++", &format!("c++ -target {zig_target}")),
+        ar: wrapper("zig-ar", "ar"),
+        ranlib: wrapper("zig-ranlib", "ranlib"),
+        toolchain_file: dir.join("zig-toolchain.cmake"),
+    };
+
+    let os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let (system_name, system_processor) = crate::cmake::cmake_system(&os, &arch);
+    ToolchainFile {
+        system: Some((system_name.to_string(), system_processor.to_string())),
+        c_compiler: Some(toolchain.cc.clone()),
+        cxx_compiler: Some(toolchain.cxx.clone()),
+        ar: Some(toolchain.ar.clone()),
+        ranlib: Some(toolchain.ranlib.clone()),
+        ..Default::default()
+    }
+    .write(&toolchain.toolchain_file);
+
+    print_once(&format!("cargo:zig_cc={}", toolchain.cc.display()));
+    toolchain
+}
+
+/// Points `build` at the compilers of [`LConfig::toolchain`]. For
+/// [`Toolchain::Default`] this only picks a musl targeting compiler when
+/// cross compiling to musl (see [`musl::compiler`]).
+pub fn configure_cc(lcfg: &LConfig, build: &mut cc::Build, cpp: bool) {
+    let target = std::env::var("TARGET").unwrap();
+    match lcfg.toolchain {
+        Toolchain::Zig => {
+            let zig = zig_toolchain(&target);
+            build
+                .compiler(if cpp { &zig.cxx } else { &zig.cc })
+                .archiver(&zig.ar)
+                .ranlib(&zig.ranlib);
+        }
+        Toolchain::Default => {
+            if let Some(compiler) = musl::compiler(&target, cpp) {
+                build.compiler(compiler);
+            }
+        }
+    }
+}
+
+// CMake wants forward slashes, even on Windows
+fn cmake_path(path: &Path) -> String {
+    path.display().to_string().replace('\\', "/")
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .unwrap_or_else(|e| panic!("Failed to make {} executable: {}", path.display(), e));
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zig_targets() {
+        let cases = [
+            ("x86_64-unknown-linux-gnu", Some("x86_64-linux-gnu")),
+            ("aarch64-unknown-linux-musl", Some("aarch64-linux-musl")),
+            ("armv7-unknown-linux-gnueabihf", Some("arm-linux-gnueabihf")),
+            ("i686-unknown-linux-gnu", Some("x86-linux-gnu")),
+            ("riscv64gc-unknown-linux-gnu", Some("riscv64-linux-gnu")),
+            ("aarch64-apple-darwin", Some("aarch64-macos")),
+            ("x86_64-pc-windows-gnu", Some("x86_64-windows-gnu")),
+            ("x86_64-unknown-freebsd", Some("x86_64-freebsd")),
+            ("wasm32-wasip1", Some("wasm32-wasi")),
+            ("aarch64-linux-android", None),
+            ("x86_64-pc-windows-msvc", None),
+            ("aarch64-apple-ios", None),
+        ];
+        for (rust_target, zig) in cases {
+            assert_eq!(zig_target(rust_target).as_deref(), zig, "{rust_target}");
+        }
+    }
+
+    #[test]
+    fn find_root_modes_need_a_find_root_path() {
+        let file = ToolchainFile {
+            system: Some(("Linux".to_string(), "aarch64".to_string())),
+            c_compiler: Some(PathBuf::from("clang")),
+            ..Default::default()
+        };
+        assert!(!file.contents().contains("CMAKE_FIND_ROOT_PATH"));
+
+        let file = ToolchainFile {
+            sysroot: Some(PathBuf::from("/usr/aarch64-linux-gnu")),
+            find_root_path: Some(vec![PathBuf::from("/usr/aarch64-linux-gnu")]),
+            ..file
+        };
+        let contents = file.contents();
+        assert!(contents.contains("set(CMAKE_FIND_ROOT_PATH \"/usr/aarch64-linux-gnu\")"));
+        assert!(contents.contains("set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY ONLY)"));
+    }
+}
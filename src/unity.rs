@@ -0,0 +1,149 @@
+//! Unity (jumbo) builds: several source files are `#include`d into one
+//! generated translation unit, which speeds up compiling Luau's Analysis and
+//! Lute's runtime with their hundreds of small files considerably.
+//!
+//! For `cc` builds the batches are generated here, for CMake builds the same
+//! settings are forwarded through `CMAKE_UNITY_BUILD` together with a project
+//! include script marking excluded files `SKIP_UNITY_BUILD_INCLUSION`.
+
+use std::path::{Path, PathBuf};
+
+use crate::UnityBuild;
+use crate::rerun::write_if_changed;
+
+/// Files known not to combine safely with others (file-local macros or static
+/// helpers clashing across files). Always compiled on their own.
+pub const DEFAULT_UNITY_EXCLUDE: &[&str] = &[
+    "lvmexecute.cpp", // VM dispatch macros (VM_CASE, VM_NEXT, ...)
+];
+
+/// Returns true if `file` must not be combined with other files
+pub fn is_excluded(file: &Path, unity: &UnityBuild) -> bool {
+    DEFAULT_UNITY_EXCLUDE
+        .iter()
+        .chain(unity.exclude)
+        .any(|pattern| file.ends_with(pattern))
+}
+
+/// Groups the C++ files among `files` into batches of `unity.batch_size`
+/// generated under `OUT_DIR/unity/<name>`, returning the list of files to
+/// compile instead. Excluded and non C++ files are passed through unchanged.
+pub fn unity_sources(name: &str, files: &[String], unity: &UnityBuild) -> Vec<String> {
+    let unity_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap())
+        .join("unity")
+        .join(name);
+    std::fs::create_dir_all(&unity_dir).expect("Failed to create unity build directory");
+    let cwd = std::env::current_dir().unwrap();
+
+    let mut sources = Vec::new();
+    let mut batchable = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        if path.extension().is_some_and(|ext| ext == "cpp") && !is_excluded(path, unity) {
+            batchable.push(cwd.join(path));
+        } else {
+            sources.push(file.clone());
+        }
+    }
+
+    for (i, batch) in batchable.chunks(unity.batch_size.max(1)).enumerate() {
+        let mut contents = String::from("// Generated by lute-src-rs-common, do not edit\n");
+        for file in batch {
+            // Forward slashes keep Windows paths valid inside the string literal
+            let file = file.display().to_string().replace('\\', "/");
+            contents.push_str(&format!("#include \"{file}\"\n"));
+        }
+
+        let unity_file = unity_dir.join(format!("unity_{i}.cpp"));
+        write_if_changed(&unity_file, contents);
+        sources.push(unity_file.display().to_string());
+    }
+
+    sources
+}
+
+/// Writes a CMake script (meant for `CMAKE_PROJECT_INCLUDE`) that marks the
+/// excluded files of every target `SKIP_UNITY_BUILD_INCLUSION`, returning its
+/// path.
+///
+/// Only the `Luau.*`/`Lute.*` targets are built in unity mode, third party
+/// dependencies (libuv, BoringSSL, curl, ...) are left alone.
+pub fn write_cmake_exclude_script(out_dir: &Path, unity: &UnityBuild) -> PathBuf {
+    let patterns = DEFAULT_UNITY_EXCLUDE
+        .iter()
+        .chain(unity.exclude)
+        .map(|pattern| cmake_regex_escape(pattern))
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let script = format!(
+        r#"# Generated by lute-src-rs-common, do not edit
+include_guard(GLOBAL)
+
+if(CMAKE_VERSION VERSION_LESS 3.19)
+  message(WARNING "Unity build exclusions require CMake 3.19, building without them")
+  return()
+endif()
+
+set(LUTE_UNITY_EXCLUDE "{patterns}")
+
+function(lute_unity_exclude_dir dir)
+  get_property(targets DIRECTORY "${{dir}}" PROPERTY BUILDSYSTEM_TARGETS)
+  foreach(target IN LISTS targets)
+    if(NOT target MATCHES "^(Luau|Lute)\\.")
+      set_property(TARGET ${{target}} PROPERTY UNITY_BUILD OFF)
+      continue()
+    endif()
+    get_target_property(sources ${{target}} SOURCES)
+    if(NOT sources)
+      continue()
+    endif()
+    get_target_property(target_dir ${{target}} SOURCE_DIR)
+    foreach(source IN LISTS sources)
+      if(source MATCHES "^\\$<")
+        continue()
+      endif()
+      if(NOT IS_ABSOLUTE "${{source}}")
+        set(source "${{target_dir}}/${{source}}")
+      endif()
+      foreach(pattern IN LISTS LUTE_UNITY_EXCLUDE)
+        if(source MATCHES "(^|/)${{pattern}}$")
+          set_source_files_properties("${{source}}" TARGET_DIRECTORY ${{target}}
+            PROPERTIES SKIP_UNITY_BUILD_INCLUSION ON)
+        endif()
+      endforeach()
+    endforeach()
+  endforeach()
+
+  get_property(subdirs DIRECTORY "${{dir}}" PROPERTY SUBDIRECTORIES)
+  foreach(subdir IN LISTS subdirs)
+    lute_unity_exclude_dir("${{subdir}}")
+  endforeach()
+endfunction()
+
+function(lute_unity_exclude)
+  lute_unity_exclude_dir("${{CMAKE_SOURCE_DIR}}")
+endfunction()
+
+# Targets are only complete once the whole project has been processed
+cmake_language(DEFER DIRECTORY "${{CMAKE_SOURCE_DIR}}" CALL lute_unity_exclude)
+"#
+    );
+
+    let path = out_dir.join("lute-unity-exclude.cmake");
+    // CMake reconfigures when an included script's timestamp changes
+    write_if_changed(&path, script);
+    path
+}
+
+// Escapes regex special characters for use in a CMake `MATCHES` expression
+fn cmake_regex_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if "^$.[]()*+?|\\".contains(c) {
+            escaped.push_str("\\\\");
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,627 @@
+//! WASI (`wasm32-wasip1`, `wasm32-wasip2`) support for the cc backend.
+//!
+//! libuv has no WASI port, so Lute is compiled against the bundled `uv.h`
+//! (with shims for the few POSIX headers wasi-libc lacks) and linked against a
+//! stub `uv` library: the event loop and clock functions work (the loop simply
+//! has nothing to run), everything else fails with `UV_ENOSYS`. Modules
+//! relying on libuv I/O therefore raise errors at runtime instead of breaking
+//! the build.
+//!
+//! The stubs are generated from [`UV_STUB_FUNCTIONS`], the libuv 1.46 API.
+//! Compiling them against the bundled `uv.h` checks their signatures, and a
+//! libuv function missing from the list fails to link naming it.
+//!
+//! The resulting modules run under wasmtime with exception handling enabled,
+//! see [`wasmtime_command`]. The ignored tests (`cargo test -- --ignored`,
+//! with `WASI_SDK_PATH` set and `wasmtime` installed) run the stub library
+//! that way, as well as the whole runtime built by the `ci/wasi-runtime`
+//! crate.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::rerun::{rerun_if_changed, write_if_changed};
+use crate::wasm::WASI_EMULATION_DEFINES;
+
+/// The bundled libuv header the stubs are compiled against
+const UV_HEADER: &str = "lute/extern/libuv/include/uv.h";
+
+// Headers included by `uv/unix.h` which are missing from (some versions of)
+// wasi-libc. The system header is used whenever it exists.
+const SHIM_HEADERS: &[(&str, &str)] = &[
+    (
+        "termios.h",
+        "typedef unsigned int tcflag_t;\n\
+         typedef unsigned char cc_t;\n\
+         typedef unsigned int speed_t;\n\
+         #define NCCS 32\n\
+         struct termios {\n  tcflag_t c_iflag, c_oflag, c_cflag, c_lflag;\n  cc_t c_cc[NCCS];\n};\n",
+    ),
+    (
+        "pwd.h",
+        "#include <sys/types.h>\n\
+         struct passwd {\n  char* pw_name;\n  char* pw_passwd;\n  uid_t pw_uid;\n  gid_t pw_gid;\n  char* pw_gecos;\n  char* pw_dir;\n  char* pw_shell;\n};\n",
+    ),
+    ("semaphore.h", "typedef struct { volatile int __val[4]; } sem_t;\n"),
+    (
+        "pthread.h",
+        "typedef unsigned long pthread_t;\n\
+         typedef unsigned int pthread_key_t;\n\
+         typedef int pthread_once_t;\n\
+         typedef struct { int __val[8]; } pthread_mutex_t;\n\
+         typedef struct { int __val[8]; } pthread_rwlock_t;\n\
+         typedef struct { int __val[12]; } pthread_cond_t;\n\
+         typedef struct { int __val[8]; } pthread_barrier_t;\n\
+         typedef struct { int __val[8]; } pthread_attr_t;\n\
+         #define PTHREAD_ONCE_INIT 0\n",
+    ),
+    (
+        "netdb.h",
+        "#include <sys/socket.h>\n\
+         struct addrinfo {\n  int ai_flags;\n  int ai_family;\n  int ai_socktype;\n  int ai_protocol;\n  socklen_t ai_addrlen;\n  struct sockaddr* ai_addr;\n  char* ai_canonname;\n  struct addrinfo* ai_next;\n};\n",
+    ),
+];
+
+/// libuv functions with a working implementation in the stub library
+pub const UV_STUB_CORE: &str = r#"
+static uv_loop_t lute_wasi_default_loop;
+static int lute_wasi_default_loop_initialized;
+
+int uv_loop_init(uv_loop_t* loop) {
+  memset(loop, 0, sizeof(*loop));
+  return 0;
+}
+
+uv_loop_t* uv_default_loop(void) {
+  if (!lute_wasi_default_loop_initialized) {
+    uv_loop_init(&lute_wasi_default_loop);
+    lute_wasi_default_loop_initialized = 1;
+  }
+  return &lute_wasi_default_loop;
+}
+
+int uv_loop_close(uv_loop_t* loop) { (void)loop; return 0; }
+int uv_loop_alive(const uv_loop_t* loop) { (void)loop; return 0; }
+int uv_run(uv_loop_t* loop, uv_run_mode mode) { (void)loop; (void)mode; return 0; }
+void uv_stop(uv_loop_t* loop) { (void)loop; }
+void uv_update_time(uv_loop_t* loop) { (void)loop; }
+
+uint64_t uv_hrtime(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return (uint64_t)ts.tv_sec * 1000000000u + (uint64_t)ts.tv_nsec;
+}
+
+uint64_t uv_now(const uv_loop_t* loop) { (void)loop; return uv_hrtime() / 1000000u; }
+
+uv_buf_t uv_buf_init(char* base, unsigned int len) {
+  uv_buf_t buf;
+  buf.base = base;
+  buf.len = len;
+  return buf;
+}
+
+const char* uv_strerror(int err) {
+  return err == UV_ENOSYS ? "function not implemented (libuv is not available on WASI)" : "unknown error";
+}
+
+const char* uv_err_name(int err) { return err == UV_ENOSYS ? "ENOSYS" : "UNKNOWN"; }
+"#;
+
+/// Writes the header shims into `OUT_DIR/wasi-shim`, returning the directory
+/// to add to the include path
+pub fn shim_include_dir() -> PathBuf {
+    let dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("wasi-shim");
+    write_shim_headers(&dir);
+    dir
+}
+
+fn write_shim_headers(dir: &Path) {
+    std::fs::create_dir_all(dir).expect("Failed to create WASI shim directory");
+    for (name, contents) in SHIM_HEADERS {
+        let guard = format!("LUTE_WASI_SHIM_{}", name.replace('.', "_").to_uppercase());
+        let header = format!(
+            "// Generated by lute-src-rs-common, do not edit\n\
+             #if __has_include_next(<{name}>)\n\
+             #include_next <{name}>\n\
+             #elif !defined({guard})\n\
+             #define {guard}\n\
+             {contents}\
+             #endif\n"
+        );
+        write_if_changed(&dir.join(name), &header);
+    }
+}
+
+/// Adds the flags needed to compile Lute (or anything including `uv.h`) for
+/// WASI to `build`
+pub fn configure_build(build: &mut cc::Build) {
+    build.include(shim_include_dir());
+    for define in WASI_EMULATION_DEFINES {
+        build.define(define, None);
+    }
+    // Luau's error handling (LUA_USE_LONGJMP) needs setjmp/longjmp, which are
+    // implemented on top of wasm exception handling
+    build.flag("-mllvm").flag("-wasm-enable-sjlj");
+}
+
+/// Returns the C build and sources of the stub libuv
+pub fn uv_stub_build() -> (cc::Build, Vec<String>) {
+    // The stubs are checked against it when compiled
+    rerun_if_changed(UV_HEADER);
+    if !Path::new(UV_HEADER).is_file() {
+        panic!("Failed to find {UV_HEADER}, is the lute submodule checked out?");
+    }
+
+    let dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("wasi-uv-stub");
+    std::fs::create_dir_all(&dir).expect("Failed to create WASI libuv stub directory");
+    let file = dir.join("uv_stub.c");
+    write_if_changed(&file, uv_stub_source());
+
+    let mut build = cc::Build::new();
+    build.cpp(false).include("lute/extern/libuv/include");
+    configure_build(&mut build);
+    (build, vec![file.display().to_string()])
+}
+
+/// Returns the C source of the stub libuv: [`UV_STUB_CORE`]'s functions, and
+/// every other one of [`UV_STUB_FUNCTIONS`] failing
+pub fn uv_stub_source() -> String {
+    let mut source = String::from(
+        "// Generated by lute-src-rs-common, do not edit\n\
+         #include <string.h>\n\
+         #include <time.h>\n\
+         #include <uv.h>\n",
+    );
+    source.push_str(UV_STUB_CORE);
+    for (ret, name, params) in UV_STUB_FUNCTIONS {
+        let body = if *ret == "void" {
+            String::new()
+        } else if *ret == "int" {
+            "return UV_ENOSYS;".to_string()
+        } else if ret.ends_with('*') {
+            "return NULL;".to_string()
+        } else {
+            format!("{ret} r; memset(&r, 0, sizeof(r)); return r;")
+        };
+        source.push_str(&format!("\n{ret} {name}({params}) {{ {body} }}\n"));
+    }
+    source
+}
+
+/// Returns a `wasmtime run` command for the WASI module (or component) at
+/// `module`, with the wasm features the Lute runtime needs. The `wasmtime`
+/// executable is taken from `WASMTIME` if set.
+pub fn wasmtime_command(module: &Path) -> Command {
+    let wasmtime = std::env::var_os("WASMTIME").unwrap_or_else(|| "wasmtime".into());
+    let mut cmd = Command::new(wasmtime);
+    // C++ exceptions and Luau's setjmp/longjmp use wasm exception handling
+    cmd.arg("run").arg("-W").arg("exceptions=y").arg(module);
+    cmd
+}
+
+/// The libuv functions stubbed out (return type, name, parameters), i.e. the
+/// API of libuv 1.46 but [`UV_STUB_CORE`]'s functions
+pub const UV_STUB_FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("unsigned int", "uv_version", "void"),
+    ("const char*", "uv_version_string", "void"),
+    ("void", "uv_library_shutdown", "void"),
+    ("int", "uv_replace_allocator", "uv_malloc_func malloc_func, uv_realloc_func realloc_func, uv_calloc_func calloc_func, uv_free_func free_func"),
+    ("uv_loop_t*", "uv_loop_new", "void"),
+    ("void", "uv_loop_delete", "uv_loop_t*"),
+    ("size_t", "uv_loop_size", "void"),
+    ("int", "uv_loop_configure", "uv_loop_t* loop, uv_loop_option option, ..."),
+    ("int", "uv_loop_fork", "uv_loop_t* loop"),
+    ("void", "uv_ref", "uv_handle_t*"),
+    ("void", "uv_unref", "uv_handle_t*"),
+    ("int", "uv_has_ref", "const uv_handle_t*"),
+    ("int", "uv_backend_fd", "const uv_loop_t*"),
+    ("int", "uv_backend_timeout", "const uv_loop_t*"),
+    ("int", "uv_translate_sys_error", "int sys_errno"),
+    ("char*", "uv_strerror_r", "int err, char* buf, size_t buflen"),
+    ("char*", "uv_err_name_r", "int err, char* buf, size_t buflen"),
+    ("int", "uv_shutdown", "uv_shutdown_t* req, uv_stream_t* handle, uv_shutdown_cb cb"),
+    ("size_t", "uv_handle_size", "uv_handle_type type"),
+    ("uv_handle_type", "uv_handle_get_type", "const uv_handle_t* handle"),
+    ("const char*", "uv_handle_type_name", "uv_handle_type type"),
+    ("void*", "uv_handle_get_data", "const uv_handle_t* handle"),
+    ("uv_loop_t*", "uv_handle_get_loop", "const uv_handle_t* handle"),
+    ("void", "uv_handle_set_data", "uv_handle_t* handle, void* data"),
+    ("size_t", "uv_req_size", "uv_req_type type"),
+    ("void*", "uv_req_get_data", "const uv_req_t* req"),
+    ("void", "uv_req_set_data", "uv_req_t* req, void* data"),
+    ("uv_req_type", "uv_req_get_type", "const uv_req_t* req"),
+    ("const char*", "uv_req_type_name", "uv_req_type type"),
+    ("int", "uv_is_active", "const uv_handle_t* handle"),
+    ("void", "uv_walk", "uv_loop_t* loop, uv_walk_cb walk_cb, void* arg"),
+    ("void", "uv_print_all_handles", "uv_loop_t* loop, FILE* stream"),
+    ("void", "uv_print_active_handles", "uv_loop_t* loop, FILE* stream"),
+    ("void", "uv_close", "uv_handle_t* handle, uv_close_cb close_cb"),
+    ("int", "uv_send_buffer_size", "uv_handle_t* handle, int* value"),
+    ("int", "uv_recv_buffer_size", "uv_handle_t* handle, int* value"),
+    ("int", "uv_fileno", "const uv_handle_t* handle, uv_os_fd_t* fd"),
+    ("int", "uv_pipe", "uv_file fds[2], int read_flags, int write_flags"),
+    ("int", "uv_socketpair", "int type, int protocol, uv_os_sock_t socket_vector[2], int flags0, int flags1"),
+    ("size_t", "uv_stream_get_write_queue_size", "const uv_stream_t* stream"),
+    ("int", "uv_listen", "uv_stream_t* stream, int backlog, uv_connection_cb cb"),
+    ("int", "uv_accept", "uv_stream_t* server, uv_stream_t* client"),
+    ("int", "uv_read_start", "uv_stream_t*, uv_alloc_cb alloc_cb, uv_read_cb read_cb"),
+    ("int", "uv_read_stop", "uv_stream_t*"),
+    ("int", "uv_write", "uv_write_t* req, uv_stream_t* handle, const uv_buf_t bufs[], unsigned int nbufs, uv_write_cb cb"),
+    ("int", "uv_write2", "uv_write_t* req, uv_stream_t* handle, const uv_buf_t bufs[], unsigned int nbufs, uv_stream_t* send_handle, uv_write_cb cb"),
+    ("int", "uv_try_write", "uv_stream_t* handle, const uv_buf_t bufs[], unsigned int nbufs"),
+    ("int", "uv_try_write2", "uv_stream_t* handle, const uv_buf_t bufs[], unsigned int nbufs, uv_stream_t* send_handle"),
+    ("int", "uv_is_readable", "const uv_stream_t* handle"),
+    ("int", "uv_is_writable", "const uv_stream_t* handle"),
+    ("int", "uv_stream_set_blocking", "uv_stream_t* handle, int blocking"),
+    ("int", "uv_is_closing", "const uv_handle_t* handle"),
+    ("int", "uv_tcp_init", "uv_loop_t*, uv_tcp_t* handle"),
+    ("int", "uv_tcp_init_ex", "uv_loop_t*, uv_tcp_t* handle, unsigned int flags"),
+    ("int", "uv_tcp_open", "uv_tcp_t* handle, uv_os_sock_t sock"),
+    ("int", "uv_tcp_nodelay", "uv_tcp_t* handle, int enable"),
+    ("int", "uv_tcp_keepalive", "uv_tcp_t* handle, int enable, unsigned int delay"),
+    ("int", "uv_tcp_simultaneous_accepts", "uv_tcp_t* handle, int enable"),
+    ("int", "uv_tcp_bind", "uv_tcp_t* handle, const struct sockaddr* addr, unsigned int flags"),
+    ("int", "uv_tcp_getsockname", "const uv_tcp_t* handle, struct sockaddr* name, int* namelen"),
+    ("int", "uv_tcp_getpeername", "const uv_tcp_t* handle, struct sockaddr* name, int* namelen"),
+    ("int", "uv_tcp_close_reset", "uv_tcp_t* handle, uv_close_cb close_cb"),
+    ("int", "uv_tcp_connect", "uv_connect_t* req, uv_tcp_t* handle, const struct sockaddr* addr, uv_connect_cb cb"),
+    ("int", "uv_udp_init", "uv_loop_t*, uv_udp_t* handle"),
+    ("int", "uv_udp_init_ex", "uv_loop_t*, uv_udp_t* handle, unsigned int flags"),
+    ("int", "uv_udp_open", "uv_udp_t* handle, uv_os_sock_t sock"),
+    ("int", "uv_udp_bind", "uv_udp_t* handle, const struct sockaddr* addr, unsigned int flags"),
+    ("int", "uv_udp_connect", "uv_udp_t* handle, const struct sockaddr* addr"),
+    ("int", "uv_udp_getpeername", "const uv_udp_t* handle, struct sockaddr* name, int* namelen"),
+    ("int", "uv_udp_getsockname", "const uv_udp_t* handle, struct sockaddr* name, int* namelen"),
+    ("int", "uv_udp_set_membership", "uv_udp_t* handle, const char* multicast_addr, const char* interface_addr, uv_membership membership"),
+    ("int", "uv_udp_set_source_membership", "uv_udp_t* handle, const char* multicast_addr, const char* interface_addr, const char* source_addr, uv_membership membership"),
+    ("int", "uv_udp_set_multicast_loop", "uv_udp_t* handle, int on"),
+    ("int", "uv_udp_set_multicast_ttl", "uv_udp_t* handle, int ttl"),
+    ("int", "uv_udp_set_multicast_interface", "uv_udp_t* handle, const char* interface_addr"),
+    ("int", "uv_udp_set_broadcast", "uv_udp_t* handle, int on"),
+    ("int", "uv_udp_set_ttl", "uv_udp_t* handle, int ttl"),
+    ("int", "uv_udp_send", "uv_udp_send_t* req, uv_udp_t* handle, const uv_buf_t bufs[], unsigned int nbufs, const struct sockaddr* addr, uv_udp_send_cb send_cb"),
+    ("int", "uv_udp_try_send", "uv_udp_t* handle, const uv_buf_t bufs[], unsigned int nbufs, const struct sockaddr* addr"),
+    ("int", "uv_udp_recv_start", "uv_udp_t* handle, uv_alloc_cb alloc_cb, uv_udp_recv_cb recv_cb"),
+    ("int", "uv_udp_using_recvmmsg", "const uv_udp_t* handle"),
+    ("int", "uv_udp_recv_stop", "uv_udp_t* handle"),
+    ("size_t", "uv_udp_get_send_queue_size", "const uv_udp_t* handle"),
+    ("size_t", "uv_udp_get_send_queue_count", "const uv_udp_t* handle"),
+    ("int", "uv_tty_init", "uv_loop_t*, uv_tty_t*, uv_file fd, int readable"),
+    ("int", "uv_tty_set_mode", "uv_tty_t*, uv_tty_mode_t mode"),
+    ("int", "uv_tty_reset_mode", "void"),
+    ("int", "uv_tty_get_winsize", "uv_tty_t*, int* width, int* height"),
+    ("void", "uv_tty_set_vterm_state", "uv_tty_vtermstate_t state"),
+    ("int", "uv_tty_get_vterm_state", "uv_tty_vtermstate_t* state"),
+    ("uv_handle_type", "uv_guess_handle", "uv_file file"),
+    ("int", "uv_pipe_init", "uv_loop_t*, uv_pipe_t* handle, int ipc"),
+    ("int", "uv_pipe_open", "uv_pipe_t*, uv_file file"),
+    ("int", "uv_pipe_bind", "uv_pipe_t* handle, const char* name"),
+    ("int", "uv_pipe_bind2", "uv_pipe_t* handle, const char* name, size_t namelen, unsigned int flags"),
+    ("void", "uv_pipe_connect", "uv_connect_t* req, uv_pipe_t* handle, const char* name, uv_connect_cb cb"),
+    ("int", "uv_pipe_connect2", "uv_connect_t* req, uv_pipe_t* handle, const char* name, size_t namelen, unsigned int flags, uv_connect_cb cb"),
+    ("int", "uv_pipe_getsockname", "const uv_pipe_t* handle, char* buffer, size_t* size"),
+    ("int", "uv_pipe_getpeername", "const uv_pipe_t* handle, char* buffer, size_t* size"),
+    ("void", "uv_pipe_pending_instances", "uv_pipe_t* handle, int count"),
+    ("int", "uv_pipe_pending_count", "uv_pipe_t* handle"),
+    ("uv_handle_type", "uv_pipe_pending_type", "uv_pipe_t* handle"),
+    ("int", "uv_pipe_chmod", "uv_pipe_t* handle, int flags"),
+    ("int", "uv_poll_init", "uv_loop_t* loop, uv_poll_t* handle, int fd"),
+    ("int", "uv_poll_init_socket", "uv_loop_t* loop, uv_poll_t* handle, uv_os_sock_t socket"),
+    ("int", "uv_poll_start", "uv_poll_t* handle, int events, uv_poll_cb cb"),
+    ("int", "uv_poll_stop", "uv_poll_t* handle"),
+    ("int", "uv_prepare_init", "uv_loop_t*, uv_prepare_t* prepare"),
+    ("int", "uv_prepare_start", "uv_prepare_t* prepare, uv_prepare_cb cb"),
+    ("int", "uv_prepare_stop", "uv_prepare_t* prepare"),
+    ("int", "uv_check_init", "uv_loop_t*, uv_check_t* check"),
+    ("int", "uv_check_start", "uv_check_t* check, uv_check_cb cb"),
+    ("int", "uv_check_stop", "uv_check_t* check"),
+    ("int", "uv_idle_init", "uv_loop_t*, uv_idle_t* idle"),
+    ("int", "uv_idle_start", "uv_idle_t* idle, uv_idle_cb cb"),
+    ("int", "uv_idle_stop", "uv_idle_t* idle"),
+    ("int", "uv_async_init", "uv_loop_t*, uv_async_t* async, uv_async_cb async_cb"),
+    ("int", "uv_async_send", "uv_async_t* async"),
+    ("int", "uv_timer_init", "uv_loop_t*, uv_timer_t* handle"),
+    ("int", "uv_timer_start", "uv_timer_t* handle, uv_timer_cb cb, uint64_t timeout, uint64_t repeat"),
+    ("int", "uv_timer_stop", "uv_timer_t* handle"),
+    ("int", "uv_timer_again", "uv_timer_t* handle"),
+    ("void", "uv_timer_set_repeat", "uv_timer_t* handle, uint64_t repeat"),
+    ("uint64_t", "uv_timer_get_repeat", "const uv_timer_t* handle"),
+    ("uint64_t", "uv_timer_get_due_in", "const uv_timer_t* handle"),
+    ("int", "uv_getaddrinfo", "uv_loop_t* loop, uv_getaddrinfo_t* req, uv_getaddrinfo_cb getaddrinfo_cb, const char* node, const char* service, const struct addrinfo* hints"),
+    ("void", "uv_freeaddrinfo", "struct addrinfo* ai"),
+    ("int", "uv_getnameinfo", "uv_loop_t* loop, uv_getnameinfo_t* req, uv_getnameinfo_cb getnameinfo_cb, const struct sockaddr* addr, int flags"),
+    ("int", "uv_spawn", "uv_loop_t* loop, uv_process_t* handle, const uv_process_options_t* options"),
+    ("int", "uv_process_kill", "uv_process_t*, int signum"),
+    ("int", "uv_kill", "int pid, int signum"),
+    ("uv_pid_t", "uv_process_get_pid", "const uv_process_t*"),
+    ("int", "uv_queue_work", "uv_loop_t* loop, uv_work_t* req, uv_work_cb work_cb, uv_after_work_cb after_work_cb"),
+    ("int", "uv_cancel", "uv_req_t* req"),
+    ("char**", "uv_setup_args", "int argc, char** argv"),
+    ("int", "uv_get_process_title", "char* buffer, size_t size"),
+    ("int", "uv_set_process_title", "const char* title"),
+    ("int", "uv_resident_set_memory", "size_t* rss"),
+    ("int", "uv_uptime", "double* uptime"),
+    ("uv_os_fd_t", "uv_get_osfhandle", "int fd"),
+    ("int", "uv_open_osfhandle", "uv_os_fd_t os_fd"),
+    ("int", "uv_getrusage", "uv_rusage_t* rusage"),
+    ("int", "uv_os_homedir", "char* buffer, size_t* size"),
+    ("int", "uv_os_tmpdir", "char* buffer, size_t* size"),
+    ("int", "uv_os_get_passwd", "uv_passwd_t* pwd"),
+    ("void", "uv_os_free_passwd", "uv_passwd_t* pwd"),
+    ("int", "uv_os_get_passwd2", "uv_passwd_t* pwd, uv_uid_t uid"),
+    ("int", "uv_os_get_group", "uv_group_t* grp, uv_uid_t gid"),
+    ("void", "uv_os_free_group", "uv_group_t* grp"),
+    ("uv_pid_t", "uv_os_getpid", "void"),
+    ("uv_pid_t", "uv_os_getppid", "void"),
+    ("int", "uv_os_getpriority", "uv_pid_t pid, int* priority"),
+    ("int", "uv_os_setpriority", "uv_pid_t pid, int priority"),
+    ("unsigned int", "uv_available_parallelism", "void"),
+    ("int", "uv_cpu_info", "uv_cpu_info_t** cpu_infos, int* count"),
+    ("void", "uv_free_cpu_info", "uv_cpu_info_t* cpu_infos, int count"),
+    ("int", "uv_cpumask_size", "void"),
+    ("int", "uv_interface_addresses", "uv_interface_address_t** addresses, int* count"),
+    ("void", "uv_free_interface_addresses", "uv_interface_address_t* addresses, int count"),
+    ("int", "uv_os_environ", "uv_env_item_t** envitems, int* count"),
+    ("void", "uv_os_free_environ", "uv_env_item_t* envitems, int count"),
+    ("int", "uv_os_getenv", "const char* name, char* buffer, size_t* size"),
+    ("int", "uv_os_setenv", "const char* name, const char* value"),
+    ("int", "uv_os_unsetenv", "const char* name"),
+    ("int", "uv_os_gethostname", "char* buffer, size_t* size"),
+    ("int", "uv_os_uname", "uv_utsname_t* buffer"),
+    ("int", "uv_metrics_info", "uv_loop_t* loop, uv_metrics_t* metrics"),
+    ("uint64_t", "uv_metrics_idle_time", "uv_loop_t* loop"),
+    ("uv_fs_type", "uv_fs_get_type", "const uv_fs_t*"),
+    ("ssize_t", "uv_fs_get_result", "const uv_fs_t*"),
+    ("int", "uv_fs_get_system_error", "const uv_fs_t*"),
+    ("void*", "uv_fs_get_ptr", "const uv_fs_t*"),
+    ("const char*", "uv_fs_get_path", "const uv_fs_t*"),
+    ("uv_stat_t*", "uv_fs_get_statbuf", "uv_fs_t*"),
+    ("void", "uv_fs_req_cleanup", "uv_fs_t* req"),
+    ("int", "uv_fs_close", "uv_loop_t* loop, uv_fs_t* req, uv_file file, uv_fs_cb cb"),
+    ("int", "uv_fs_open", "uv_loop_t* loop, uv_fs_t* req, const char* path, int flags, int mode, uv_fs_cb cb"),
+    ("int", "uv_fs_read", "uv_loop_t* loop, uv_fs_t* req, uv_file file, const uv_buf_t bufs[], unsigned int nbufs, int64_t offset, uv_fs_cb cb"),
+    ("int", "uv_fs_unlink", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_write", "uv_loop_t* loop, uv_fs_t* req, uv_file file, const uv_buf_t bufs[], unsigned int nbufs, int64_t offset, uv_fs_cb cb"),
+    ("int", "uv_fs_copyfile", "uv_loop_t* loop, uv_fs_t* req, const char* path, const char* new_path, int flags, uv_fs_cb cb"),
+    ("int", "uv_fs_mkdir", "uv_loop_t* loop, uv_fs_t* req, const char* path, int mode, uv_fs_cb cb"),
+    ("int", "uv_fs_mkdtemp", "uv_loop_t* loop, uv_fs_t* req, const char* tpl, uv_fs_cb cb"),
+    ("int", "uv_fs_mkstemp", "uv_loop_t* loop, uv_fs_t* req, const char* tpl, uv_fs_cb cb"),
+    ("int", "uv_fs_rmdir", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_scandir", "uv_loop_t* loop, uv_fs_t* req, const char* path, int flags, uv_fs_cb cb"),
+    ("int", "uv_fs_scandir_next", "uv_fs_t* req, uv_dirent_t* ent"),
+    ("int", "uv_fs_opendir", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_readdir", "uv_loop_t* loop, uv_fs_t* req, uv_dir_t* dir, uv_fs_cb cb"),
+    ("int", "uv_fs_closedir", "uv_loop_t* loop, uv_fs_t* req, uv_dir_t* dir, uv_fs_cb cb"),
+    ("int", "uv_fs_stat", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_fstat", "uv_loop_t* loop, uv_fs_t* req, uv_file file, uv_fs_cb cb"),
+    ("int", "uv_fs_rename", "uv_loop_t* loop, uv_fs_t* req, const char* path, const char* new_path, uv_fs_cb cb"),
+    ("int", "uv_fs_fsync", "uv_loop_t* loop, uv_fs_t* req, uv_file file, uv_fs_cb cb"),
+    ("int", "uv_fs_fdatasync", "uv_loop_t* loop, uv_fs_t* req, uv_file file, uv_fs_cb cb"),
+    ("int", "uv_fs_ftruncate", "uv_loop_t* loop, uv_fs_t* req, uv_file file, int64_t offset, uv_fs_cb cb"),
+    ("int", "uv_fs_sendfile", "uv_loop_t* loop, uv_fs_t* req, uv_file out_fd, uv_file in_fd, int64_t in_offset, size_t length, uv_fs_cb cb"),
+    ("int", "uv_fs_access", "uv_loop_t* loop, uv_fs_t* req, const char* path, int mode, uv_fs_cb cb"),
+    ("int", "uv_fs_chmod", "uv_loop_t* loop, uv_fs_t* req, const char* path, int mode, uv_fs_cb cb"),
+    ("int", "uv_fs_utime", "uv_loop_t* loop, uv_fs_t* req, const char* path, double atime, double mtime, uv_fs_cb cb"),
+    ("int", "uv_fs_futime", "uv_loop_t* loop, uv_fs_t* req, uv_file file, double atime, double mtime, uv_fs_cb cb"),
+    ("int", "uv_fs_lutime", "uv_loop_t* loop, uv_fs_t* req, const char* path, double atime, double mtime, uv_fs_cb cb"),
+    ("int", "uv_fs_lstat", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_link", "uv_loop_t* loop, uv_fs_t* req, const char* path, const char* new_path, uv_fs_cb cb"),
+    ("int", "uv_fs_symlink", "uv_loop_t* loop, uv_fs_t* req, const char* path, const char* new_path, int flags, uv_fs_cb cb"),
+    ("int", "uv_fs_readlink", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_realpath", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_fchmod", "uv_loop_t* loop, uv_fs_t* req, uv_file file, int mode, uv_fs_cb cb"),
+    ("int", "uv_fs_chown", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_uid_t uid, uv_gid_t gid, uv_fs_cb cb"),
+    ("int", "uv_fs_fchown", "uv_loop_t* loop, uv_fs_t* req, uv_file file, uv_uid_t uid, uv_gid_t gid, uv_fs_cb cb"),
+    ("int", "uv_fs_lchown", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_uid_t uid, uv_gid_t gid, uv_fs_cb cb"),
+    ("int", "uv_fs_statfs", "uv_loop_t* loop, uv_fs_t* req, const char* path, uv_fs_cb cb"),
+    ("int", "uv_fs_poll_init", "uv_loop_t* loop, uv_fs_poll_t* handle"),
+    ("int", "uv_fs_poll_start", "uv_fs_poll_t* handle, uv_fs_poll_cb poll_cb, const char* path, unsigned int interval"),
+    ("int", "uv_fs_poll_stop", "uv_fs_poll_t* handle"),
+    ("int", "uv_fs_poll_getpath", "uv_fs_poll_t* handle, char* buffer, size_t* size"),
+    ("int", "uv_signal_init", "uv_loop_t* loop, uv_signal_t* handle"),
+    ("int", "uv_signal_start", "uv_signal_t* handle, uv_signal_cb signal_cb, int signum"),
+    ("int", "uv_signal_start_oneshot", "uv_signal_t* handle, uv_signal_cb signal_cb, int signum"),
+    ("int", "uv_signal_stop", "uv_signal_t* handle"),
+    ("void", "uv_loadavg", "double avg[3]"),
+    ("int", "uv_fs_event_init", "uv_loop_t* loop, uv_fs_event_t* handle"),
+    ("int", "uv_fs_event_start", "uv_fs_event_t* handle, uv_fs_event_cb cb, const char* path, unsigned int flags"),
+    ("int", "uv_fs_event_stop", "uv_fs_event_t* handle"),
+    ("int", "uv_fs_event_getpath", "uv_fs_event_t* handle, char* buffer, size_t* size"),
+    ("int", "uv_ip4_addr", "const char* ip, int port, struct sockaddr_in* addr"),
+    ("int", "uv_ip6_addr", "const char* ip, int port, struct sockaddr_in6* addr"),
+    ("int", "uv_ip4_name", "const struct sockaddr_in* src, char* dst, size_t size"),
+    ("int", "uv_ip6_name", "const struct sockaddr_in6* src, char* dst, size_t size"),
+    ("int", "uv_ip_name", "const struct sockaddr* src, char* dst, size_t size"),
+    ("int", "uv_inet_ntop", "int af, const void* src, char* dst, size_t size"),
+    ("int", "uv_inet_pton", "int af, const char* src, void* dst"),
+    ("int", "uv_random", "uv_loop_t* loop, uv_random_t* req, void *buf, size_t buflen, unsigned flags, uv_random_cb cb"),
+    ("int", "uv_if_indextoname", "unsigned int ifindex, char* buffer, size_t* size"),
+    ("int", "uv_if_indextoiid", "unsigned int ifindex, char* buffer, size_t* size"),
+    ("int", "uv_exepath", "char* buffer, size_t* size"),
+    ("int", "uv_cwd", "char* buffer, size_t* size"),
+    ("int", "uv_chdir", "const char* dir"),
+    ("uint64_t", "uv_get_free_memory", "void"),
+    ("uint64_t", "uv_get_total_memory", "void"),
+    ("uint64_t", "uv_get_constrained_memory", "void"),
+    ("uint64_t", "uv_get_available_memory", "void"),
+    ("int", "uv_clock_gettime", "uv_clock_id clock_id, uv_timespec64_t* ts"),
+    ("void", "uv_sleep", "unsigned int msec"),
+    ("void", "uv_disable_stdio_inheritance", "void"),
+    ("int", "uv_dlopen", "const char* filename, uv_lib_t* lib"),
+    ("void", "uv_dlclose", "uv_lib_t* lib"),
+    ("int", "uv_dlsym", "uv_lib_t* lib, const char* name, void** ptr"),
+    ("const char*", "uv_dlerror", "const uv_lib_t* lib"),
+    ("int", "uv_mutex_init", "uv_mutex_t* handle"),
+    ("int", "uv_mutex_init_recursive", "uv_mutex_t* handle"),
+    ("void", "uv_mutex_destroy", "uv_mutex_t* handle"),
+    ("void", "uv_mutex_lock", "uv_mutex_t* handle"),
+    ("int", "uv_mutex_trylock", "uv_mutex_t* handle"),
+    ("void", "uv_mutex_unlock", "uv_mutex_t* handle"),
+    ("int", "uv_rwlock_init", "uv_rwlock_t* rwlock"),
+    ("void", "uv_rwlock_destroy", "uv_rwlock_t* rwlock"),
+    ("void", "uv_rwlock_rdlock", "uv_rwlock_t* rwlock"),
+    ("int", "uv_rwlock_tryrdlock", "uv_rwlock_t* rwlock"),
+    ("void", "uv_rwlock_rdunlock", "uv_rwlock_t* rwlock"),
+    ("void", "uv_rwlock_wrlock", "uv_rwlock_t* rwlock"),
+    ("int", "uv_rwlock_trywrlock", "uv_rwlock_t* rwlock"),
+    ("void", "uv_rwlock_wrunlock", "uv_rwlock_t* rwlock"),
+    ("int", "uv_sem_init", "uv_sem_t* sem, unsigned int value"),
+    ("void", "uv_sem_destroy", "uv_sem_t* sem"),
+    ("void", "uv_sem_post", "uv_sem_t* sem"),
+    ("void", "uv_sem_wait", "uv_sem_t* sem"),
+    ("int", "uv_sem_trywait", "uv_sem_t* sem"),
+    ("int", "uv_cond_init", "uv_cond_t* cond"),
+    ("void", "uv_cond_destroy", "uv_cond_t* cond"),
+    ("void", "uv_cond_signal", "uv_cond_t* cond"),
+    ("void", "uv_cond_broadcast", "uv_cond_t* cond"),
+    ("int", "uv_barrier_init", "uv_barrier_t* barrier, unsigned int count"),
+    ("void", "uv_barrier_destroy", "uv_barrier_t* barrier"),
+    ("int", "uv_barrier_wait", "uv_barrier_t* barrier"),
+    ("void", "uv_cond_wait", "uv_cond_t* cond, uv_mutex_t* mutex"),
+    ("int", "uv_cond_timedwait", "uv_cond_t* cond, uv_mutex_t* mutex, uint64_t timeout"),
+    ("void", "uv_once", "uv_once_t* guard, void (*callback)(void)"),
+    ("int", "uv_key_create", "uv_key_t* key"),
+    ("void", "uv_key_delete", "uv_key_t* key"),
+    ("void*", "uv_key_get", "uv_key_t* key"),
+    ("void", "uv_key_set", "uv_key_t* key, void* value"),
+    ("int", "uv_gettimeofday", "uv_timeval64_t* tv"),
+    ("int", "uv_thread_create", "uv_thread_t* tid, uv_thread_cb entry, void* arg"),
+    ("int", "uv_thread_create_ex", "uv_thread_t* tid, const uv_thread_options_t* params, uv_thread_cb entry, void* arg"),
+    ("int", "uv_thread_setaffinity", "uv_thread_t* tid, char* cpumask, char* oldmask, size_t mask_size"),
+    ("int", "uv_thread_getaffinity", "uv_thread_t* tid, char* cpumask, size_t mask_size"),
+    ("int", "uv_thread_getcpu", "void"),
+    ("uv_thread_t", "uv_thread_self", "void"),
+    ("int", "uv_thread_join", "uv_thread_t *tid"),
+    ("int", "uv_thread_equal", "const uv_thread_t* t1, const uv_thread_t* t2"),
+    ("void*", "uv_loop_get_data", "const uv_loop_t*"),
+    ("void", "uv_loop_set_data", "uv_loop_t*, void* data"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The directory containing the `uv.h` to check the stubs against: the
+    // bundled one, or `LUTE_UV_INCLUDE` (e.g. a system libuv 1.46)
+    fn uv_include_dir() -> PathBuf {
+        std::env::var_os("LUTE_UV_INCLUDE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(UV_HEADER).parent().unwrap().to_path_buf())
+            .canonicalize()
+            .ok()
+            .filter(|dir| dir.join("uv.h").is_file())
+            .expect("No uv.h: check out the lute submodule or set LUTE_UV_INCLUDE")
+    }
+
+    fn wasi_sdk() -> PathBuf {
+        crate::wasm::wasi_sdk_path().expect("Set WASI_SDK_PATH to a wasi-sdk installation")
+    }
+
+    // Runs the WASI module at `module` under wasmtime, panicking if it fails
+    fn run_under_wasmtime(module: &Path) {
+        let status = wasmtime_command(module)
+            .status()
+            .expect("Failed to run wasmtime, install it or set WASMTIME");
+        assert!(status.success(), "{} failed under wasmtime: {status}", module.display());
+    }
+
+    fn write_stub(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let file = dir.join("uv_stub.c");
+        write_if_changed(&file, uv_stub_source());
+        file
+    }
+
+    #[test]
+    fn stub_functions_are_unique() {
+        let mut names = UV_STUB_FUNCTIONS.iter().map(|(_, name, _)| *name).collect::<Vec<_>>();
+        names.sort_unstable();
+        let count = names.len();
+        names.dedup();
+        assert_eq!(names.len(), count);
+        for name in names {
+            assert!(!UV_STUB_CORE.contains(&format!(" {name}(")), "{name} is stubbed twice");
+        }
+    }
+
+    #[test]
+    #[ignore = "needs uv.h (the lute submodule or LUTE_UV_INCLUDE)"]
+    fn stub_matches_uv_h() {
+        let include = uv_include_dir();
+        let dir = std::env::temp_dir().join(format!("lute-wasi-stub-{}", std::process::id()));
+        let file = write_stub(&dir);
+        // Conflicting types or a stub for a function uv.h doesn't declare fail
+        let output = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+            .args(["-fsyntax-only", "-std=gnu11", "-Werror=missing-prototypes", "-I"])
+            .arg(&include)
+            .arg(&file)
+            .output()
+            .expect("Failed to run the C compiler");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    #[ignore = "needs uv.h, WASI_SDK_PATH and wasmtime"]
+    fn stub_runs_under_wasmtime() {
+        let (sdk, include) = (wasi_sdk(), uv_include_dir());
+        let dir = std::env::temp_dir().join(format!("lute-wasi-run-{}", std::process::id()));
+        let stub = write_stub(&dir);
+        write_shim_headers(&dir.join("shim"));
+        let main = dir.join("main.c");
+        std::fs::write(
+            &main,
+            "#include <uv.h>\n\
+             int main(void) {\n\
+               uv_fs_t req;\n\
+               if (uv_fs_open(uv_default_loop(), &req, \"x\", 0, 0, NULL) != UV_ENOSYS) return 1;\n\
+               if (uv_run(uv_default_loop(), UV_RUN_DEFAULT) != 0) return 2;\n\
+               if (uv_hrtime() == 0) return 3;\n\
+               return 0;\n\
+             }\n",
+        )
+        .unwrap();
+        let module = dir.join("stub.wasm");
+        let mut clang = Command::new(sdk.join("bin").join("clang"));
+        clang
+            .arg("--target=wasm32-wasip1")
+            .arg("-I")
+            .arg(dir.join("shim"))
+            .arg("-I")
+            .arg(&include)
+            .args(WASI_EMULATION_DEFINES.iter().map(|define| format!("-D{define}")))
+            .args(crate::wasm::WASI_EMULATION_LIBS.iter().map(|lib| format!("-l{lib}")))
+            .arg(&stub)
+            .arg(&main)
+            .arg("-o")
+            .arg(&module);
+        let status = clang.status().expect("Failed to run the wasi-sdk clang");
+        assert!(status.success(), "Failed to compile the stub for WASI");
+
+        run_under_wasmtime(&module);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[ignore = "needs the lute submodule, WASI_SDK_PATH, the wasm32-wasip1 Rust target and wasmtime"]
+    fn runtime_runs_under_wasmtime() {
+        let sdk = wasi_sdk();
+        uv_include_dir();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let target_dir = root.join("target").join("wasi-runtime");
+        let status = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+            .args(["build", "--release", "--target", "wasm32-wasip1", "--manifest-path"])
+            .arg(root.join("ci").join("wasi-runtime").join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(&target_dir)
+            .env("CC_wasm32_wasip1", sdk.join("bin").join("clang"))
+            .env("CXX_wasm32_wasip1", sdk.join("bin").join("clang++"))
+            .env("AR_wasm32_wasip1", sdk.join("bin").join("llvm-ar"))
+            .status()
+            .expect("Failed to run cargo");
+        assert!(status.success(), "Failed to build the Lute runtime for WASI");
+
+        run_under_wasmtime(&target_dir.join("wasm32-wasip1").join("release").join("lute-wasi-runtime-check.wasm"));
+    }
+}
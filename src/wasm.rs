@@ -0,0 +1,163 @@
+//! WebAssembly targets.
+//!
+//! On Emscripten (`wasm32-unknown-emscripten`) Lute runs in the browser or
+//! under node: there is no socket support usable by Lute.Net and no native
+//! code generation, so those modules are forced off. C++ exceptions are either
+//! Emscripten's JavaScript based ones or native wasm exceptions, see
+//! [`WasmExceptions`], and the matching compile and link flags are emitted.
+//!
+//! The link flags ([`link_args`], from [`EmscriptenConfig`](crate::EmscriptenConfig))
+//! must be passed when linking the final `.js`/`.wasm`, but link arguments
+//! printed by a build script only apply to its own package. They are therefore
+//! exported as the space separated `link_args`
+//! [cargo metadata](crate#metadata-for-dependents) key (`DEP_LUTE_LINK_ARGS`), which
+//! the build script of the final binary has to print:
+//!
+//! ```no_run
+//! for arg in std::env::var("DEP_LUTE_LINK_ARGS").unwrap_or_default().split_whitespace() {
+//!     println!("cargo:rustc-link-arg={arg}");
+//! }
+//! ```
+//!
+//! On WASI (`wasm32-wasip1`, `wasm32-wasip2`) the runtime is built with the
+//! wasi-sdk toolchain (found through `WASI_SDK_PATH`) by the cc backend, with
+//! libuv replaced by a stub library (see the `wasi` module). Besides net and
+//! native codegen, crypto is turned off as BoringSSL does not support WASI.
+//! C++ exceptions and Luau's longjmp based error handling use the wasm
+//! exception handling proposal, so this needs a wasi-sdk shipping libc++ with
+//! exceptions enabled and a runtime supporting the proposal (e.g. a recent
+//! wasmtime with exceptions enabled).
+
+use std::path::PathBuf;
+
+use crate::rerun::{rerun_if_env_changed, warn_once};
+use crate::{Backend, LConfig, WasmExceptions};
+
+/// Defines enabling wasi-libc's emulation of POSIX signals, process clocks,
+/// mmap and getpid (used by libuv headers and Lute)
+pub const WASI_EMULATION_DEFINES: &[&str] = &[
+    "_WASI_EMULATED_SIGNAL",
+    "_WASI_EMULATED_PROCESS_CLOCKS",
+    "_WASI_EMULATED_MMAN",
+    "_WASI_EMULATED_GETPID",
+];
+
+/// Libraries implementing [`WASI_EMULATION_DEFINES`], to link
+pub const WASI_EMULATION_LIBS: &[&str] = &[
+    "wasi-emulated-signal",
+    "wasi-emulated-process-clocks",
+    "wasi-emulated-mman",
+    "wasi-emulated-getpid",
+];
+
+/// Returns true if `target` is an Emscripten target
+pub fn is_emscripten(target: &str) -> bool {
+    target.ends_with("emscripten")
+}
+
+/// Returns true if `target` is a WebAssembly target
+pub fn is_wasm(target: &str) -> bool {
+    target.starts_with("wasm")
+}
+
+/// Returns true if `target` is a WASI target
+pub fn is_wasi(target: &str) -> bool {
+    target.contains("-wasi")
+}
+
+/// Returns the wasi-sdk install directory (`WASI_SDK_PATH`)
+pub fn wasi_sdk_path() -> Option<PathBuf> {
+    rerun_if_env_changed("WASI_SDK_PATH");
+    std::env::var_os("WASI_SDK_PATH").map(PathBuf::from)
+}
+
+/// Returns the wasi-sdk sysroot library directory for `target` (containing
+/// libc++ and the WASI emulation libraries), if `WASI_SDK_PATH` is set
+pub fn wasi_sysroot_lib_dir(target: &str) -> Option<PathBuf> {
+    Some(wasi_sdk_path()?.join("share").join("wasi-sysroot").join("lib").join(target))
+}
+
+/// Returns the compiler flag enabling C++ exceptions for `target`
+pub fn exceptions_flag(lcfg: &LConfig, target: &str) -> &'static str {
+    if (is_emscripten(target) && lcfg.emscripten.exceptions == WasmExceptions::Native) || is_wasi(target) {
+        "-fwasm-exceptions"
+    } else {
+        "-fexceptions"
+    }
+}
+
+/// Turns off the modules `target` can't support, see [`LConfig::for_target`]
+pub(crate) fn restrict_lconfig(mut lcfg: LConfig, target: &str) -> LConfig {
+    if !is_wasm(target) {
+        return lcfg;
+    }
+
+    if !lcfg.disable_net {
+        warn_once(&format!(
+            "Lute.Net is not supported on {target} (no socket support), building without it"
+        ));
+        lcfg.disable_net = true;
+    }
+    if !lcfg.disable_native_codegen {
+        warn_once(&format!(
+            "Luau native code generation is not supported on {target}, building without it"
+        ));
+        lcfg.disable_native_codegen = true;
+    }
+    if is_wasi(target) {
+        if !lcfg.disable_crypto {
+            warn_once(&format!(
+                "Lute.Crypto is not supported on {target} (BoringSSL does not support WASI), building without it"
+            ));
+            lcfg.disable_crypto = true;
+        }
+        // libuv's CMake project can't be configured for WASI: every build
+        // entry point (setup_lute_cmake included) dispatches on the backend
+        // after this
+        lcfg.backend = Backend::Cc;
+    }
+    lcfg
+}
+
+/// Returns the link arguments Lute needs for `target` (none contains spaces),
+/// see the module documentation
+pub fn link_args(lcfg: &LConfig, target: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    if !is_emscripten(target) {
+        return args;
+    }
+
+    let emscripten = &lcfg.emscripten;
+    // Exceptions have to be enabled at link time too
+    args.push(exceptions_flag(lcfg, target).to_string());
+    if emscripten.allow_memory_growth {
+        args.push("-sALLOW_MEMORY_GROWTH=1".to_string());
+    }
+    if let Some(stack_size) = emscripten.stack_size {
+        args.push(format!("-sSTACK_SIZE={stack_size}"));
+    }
+    if !emscripten.exported_functions.is_empty() {
+        let functions = emscripten
+            .exported_functions
+            .iter()
+            .map(|f| if f.starts_with('_') { f.to_string() } else { format!("_{f}") })
+            .collect::<Vec<_>>();
+        args.push(format!("-sEXPORTED_FUNCTIONS={}", functions.join(",")));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasi_builds_use_the_cc_backend() {
+        let lcfg = LConfig::default().for_target("wasm32-wasip1");
+        assert_eq!(lcfg.backend, Backend::Cc);
+        assert!(lcfg.disable_net && lcfg.disable_crypto && lcfg.disable_native_codegen);
+
+        let lcfg = LConfig::default().for_target("wasm32-unknown-emscripten");
+        assert_eq!(lcfg.backend, Backend::CMake);
+    }
+}